@@ -0,0 +1,13 @@
+CREATE TABLE price_update_runs (
+    id BIGSERIAL PRIMARY KEY,
+    parent_id BIGINT REFERENCES price_update_runs(id) ON DELETE CASCADE,
+    supplier_api_key UUID REFERENCES suppliers(api_key) ON DELETE CASCADE,
+    started_at TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+    finished_at TIMESTAMPTZ,
+    products_checked INTEGER NOT NULL DEFAULT 0,
+    products_changed INTEGER NOT NULL DEFAULT 0,
+    error_class TEXT,
+    error_message TEXT
+);
+
+CREATE INDEX price_update_runs_supplier_idx ON price_update_runs (supplier_api_key, started_at DESC);
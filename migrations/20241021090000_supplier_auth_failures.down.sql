@@ -0,0 +1,3 @@
+ALTER TABLE suppliers
+    DROP COLUMN IF EXISTS auth_failures,
+    DROP COLUMN IF EXISTS needs_reauth;
@@ -0,0 +1,3 @@
+ALTER TABLE suppliers
+    ADD COLUMN auth_failures INTEGER NOT NULL DEFAULT 0,
+    ADD COLUMN needs_reauth BOOLEAN NOT NULL DEFAULT FALSE;
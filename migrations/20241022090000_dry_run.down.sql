@@ -0,0 +1,5 @@
+DROP TABLE IF EXISTS planned_price_changes;
+
+ALTER TABLE price_update_runs DROP COLUMN IF EXISTS dry_run;
+
+ALTER TABLE suppliers DROP COLUMN IF EXISTS dry_run;
@@ -0,0 +1,17 @@
+ALTER TABLE suppliers ADD COLUMN dry_run BOOLEAN NOT NULL DEFAULT FALSE;
+
+ALTER TABLE price_update_runs ADD COLUMN dry_run BOOLEAN NOT NULL DEFAULT FALSE;
+
+CREATE TABLE planned_price_changes (
+    id BIGSERIAL PRIMARY KEY,
+    run_id BIGINT REFERENCES price_update_runs(id) ON DELETE CASCADE,
+    supplier_api_key UUID NOT NULL REFERENCES suppliers(api_key) ON DELETE CASCADE,
+    product_id INTEGER NOT NULL,
+    target_price INTEGER NOT NULL,
+    observed_basic INTEGER NOT NULL,
+    observed_total INTEGER NOT NULL,
+    new_price INTEGER NOT NULL,
+    created_at TIMESTAMPTZ NOT NULL DEFAULT NOW()
+);
+
+CREATE INDEX planned_price_changes_supplier_idx ON planned_price_changes (supplier_api_key, created_at DESC);
@@ -0,0 +1,3 @@
+ALTER TABLE products DROP COLUMN IF EXISTS active;
+
+ALTER TABLE suppliers DROP COLUMN IF EXISTS active;
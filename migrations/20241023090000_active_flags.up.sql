@@ -0,0 +1,3 @@
+ALTER TABLE suppliers ADD COLUMN active BOOLEAN NOT NULL DEFAULT TRUE;
+
+ALTER TABLE products ADD COLUMN active BOOLEAN NOT NULL DEFAULT TRUE;
@@ -0,0 +1,3 @@
+DROP TABLE IF EXISTS price_schedules;
+
+ALTER TABLE suppliers DROP COLUMN IF EXISTS timezone;
@@ -0,0 +1,16 @@
+ALTER TABLE suppliers ADD COLUMN timezone TEXT NOT NULL DEFAULT 'Europe/Moscow';
+
+CREATE TABLE price_schedules (
+    id BIGSERIAL PRIMARY KEY,
+    product_id INTEGER NOT NULL REFERENCES products(id) ON DELETE CASCADE,
+    supplier_api_key UUID NOT NULL REFERENCES suppliers(api_key) ON DELETE CASCADE,
+    position INTEGER NOT NULL,
+    weekdays SMALLINT[],
+    start_date DATE,
+    end_date DATE,
+    start_time TIME NOT NULL,
+    end_time TIME NOT NULL,
+    price INTEGER NOT NULL
+);
+
+CREATE INDEX price_schedules_supplier_idx ON price_schedules (supplier_api_key, product_id, position);
@@ -0,0 +1,18 @@
+CREATE TABLE campaigns (
+    id BIGSERIAL PRIMARY KEY,
+    supplier_api_key UUID NOT NULL REFERENCES suppliers(api_key) ON DELETE CASCADE,
+    name TEXT NOT NULL,
+    starts_at TIMESTAMPTZ NOT NULL,
+    ends_at TIMESTAMPTZ NOT NULL,
+    created_at TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+    CHECK (starts_at < ends_at)
+);
+
+CREATE INDEX campaigns_supplier_idx ON campaigns (supplier_api_key, ends_at);
+
+CREATE TABLE campaign_products (
+    campaign_id BIGINT NOT NULL REFERENCES campaigns(id) ON DELETE CASCADE,
+    product_id INTEGER NOT NULL REFERENCES products(id) ON DELETE CASCADE,
+    price INTEGER NOT NULL,
+    PRIMARY KEY (campaign_id, product_id)
+);
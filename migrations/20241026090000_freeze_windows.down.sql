@@ -0,0 +1,3 @@
+ALTER TABLE price_update_runs DROP COLUMN IF EXISTS frozen;
+
+DROP TABLE IF EXISTS freeze_windows;
@@ -0,0 +1,13 @@
+CREATE TABLE freeze_windows (
+    id BIGSERIAL PRIMARY KEY,
+    supplier_api_key UUID NOT NULL REFERENCES suppliers(api_key) ON DELETE CASCADE,
+    starts_at TIMESTAMPTZ NOT NULL,
+    ends_at TIMESTAMPTZ NOT NULL,
+    reason TEXT,
+    created_at TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+    CHECK (starts_at < ends_at)
+);
+
+CREATE INDEX freeze_windows_supplier_idx ON freeze_windows (supplier_api_key, ends_at);
+
+ALTER TABLE price_update_runs ADD COLUMN frozen BOOLEAN NOT NULL DEFAULT FALSE;
@@ -0,0 +1,14 @@
+ALTER TABLE campaign_products DROP CONSTRAINT campaign_products_product_fkey;
+ALTER TABLE campaign_products DROP COLUMN supplier_api_key;
+ALTER TABLE price_schedules DROP CONSTRAINT price_schedules_product_fkey;
+
+ALTER TABLE products DROP CONSTRAINT products_pkey;
+ALTER TABLE products ADD PRIMARY KEY (id);
+ALTER TABLE products ALTER COLUMN supplier_api_key DROP NOT NULL;
+
+ALTER TABLE price_schedules
+    ADD CONSTRAINT price_schedules_product_id_fkey
+    FOREIGN KEY (product_id) REFERENCES products(id) ON DELETE CASCADE;
+ALTER TABLE campaign_products
+    ADD CONSTRAINT campaign_products_product_id_fkey
+    FOREIGN KEY (product_id) REFERENCES products(id) ON DELETE CASCADE;
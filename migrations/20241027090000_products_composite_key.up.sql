@@ -0,0 +1,26 @@
+ALTER TABLE price_schedules DROP CONSTRAINT price_schedules_product_id_fkey;
+ALTER TABLE campaign_products DROP CONSTRAINT campaign_products_product_id_fkey;
+
+-- goods without a supplier can't be keyed by supplier, leave it to an operator to reassign or delete them
+DO $$
+BEGIN
+    IF EXISTS (SELECT 1 FROM products WHERE supplier_api_key IS NULL) THEN
+        RAISE EXCEPTION 'products has rows without supplier_api_key, assign or delete them before migrating';
+    END IF;
+END $$;
+
+ALTER TABLE products ALTER COLUMN supplier_api_key SET NOT NULL;
+ALTER TABLE products DROP CONSTRAINT products_pkey;
+ALTER TABLE products ADD PRIMARY KEY (supplier_api_key, id);
+
+ALTER TABLE price_schedules
+    ADD CONSTRAINT price_schedules_product_fkey
+    FOREIGN KEY (supplier_api_key, product_id) REFERENCES products(supplier_api_key, id) ON DELETE CASCADE;
+
+ALTER TABLE campaign_products ADD COLUMN supplier_api_key UUID;
+UPDATE campaign_products cp SET supplier_api_key = c.supplier_api_key
+FROM campaigns c WHERE c.id = cp.campaign_id;
+ALTER TABLE campaign_products ALTER COLUMN supplier_api_key SET NOT NULL;
+ALTER TABLE campaign_products
+    ADD CONSTRAINT campaign_products_product_fkey
+    FOREIGN KEY (supplier_api_key, product_id) REFERENCES products(supplier_api_key, id) ON DELETE CASCADE;
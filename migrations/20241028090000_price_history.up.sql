@@ -0,0 +1,14 @@
+CREATE TABLE price_history (
+    id BIGSERIAL PRIMARY KEY,
+    supplier_api_key UUID NOT NULL REFERENCES suppliers(api_key) ON DELETE CASCADE,
+    product_id INTEGER NOT NULL,
+    kind TEXT NOT NULL CHECK (kind IN ('observed', 'uploaded')),
+    basic INTEGER,
+    total INTEGER,
+    spp NUMERIC(5, 2),
+    price INTEGER,
+    recorded_at TIMESTAMPTZ NOT NULL DEFAULT NOW()
+);
+
+CREATE INDEX price_history_product_idx ON price_history (supplier_api_key, product_id, kind, recorded_at);
+CREATE INDEX price_history_recorded_at_idx ON price_history (recorded_at);
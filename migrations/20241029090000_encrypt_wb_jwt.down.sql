@@ -0,0 +1,4 @@
+ALTER TABLE suppliers
+    DROP COLUMN IF EXISTS wb_jwt_ciphertext,
+    DROP COLUMN IF EXISTS wb_jwt_dek,
+    DROP COLUMN IF EXISTS wb_jwt_key_id;
@@ -0,0 +1,4 @@
+ALTER TABLE suppliers
+    ADD COLUMN wb_jwt_ciphertext BYTEA,
+    ADD COLUMN wb_jwt_dek BYTEA,
+    ADD COLUMN wb_jwt_key_id TEXT;
@@ -0,0 +1,13 @@
+CREATE TABLE audit_log (
+    id BIGSERIAL PRIMARY KEY,
+    supplier_api_key UUID REFERENCES suppliers(api_key) ON DELETE CASCADE,
+    actor TEXT NOT NULL,
+    action TEXT NOT NULL,
+    target TEXT,
+    old_value JSONB,
+    new_value JSONB,
+    client_ip TEXT,
+    created_at TIMESTAMPTZ NOT NULL DEFAULT NOW()
+);
+
+CREATE INDEX audit_log_supplier_idx ON audit_log (supplier_api_key, created_at DESC);
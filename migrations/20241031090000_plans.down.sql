@@ -0,0 +1,3 @@
+ALTER TABLE suppliers DROP COLUMN plan;
+
+DROP TABLE plans;
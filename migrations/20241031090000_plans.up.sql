@@ -0,0 +1,11 @@
+CREATE TABLE plans (
+    id TEXT PRIMARY KEY,
+    max_products INTEGER NOT NULL,
+    min_update_interval INTEGER NOT NULL DEFAULT 0,
+    features TEXT[] NOT NULL DEFAULT '{}'
+);
+
+INSERT INTO plans (id, max_products, min_update_interval, features)
+VALUES ('default', 100, 0, '{dry_run,schedules,campaigns,freezes,bulk_goods}');
+
+ALTER TABLE suppliers ADD COLUMN plan TEXT NOT NULL DEFAULT 'default' REFERENCES plans(id);
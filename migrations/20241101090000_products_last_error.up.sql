@@ -0,0 +1 @@
+ALTER TABLE products ADD COLUMN last_error TEXT;
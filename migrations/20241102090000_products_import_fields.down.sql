@@ -0,0 +1,6 @@
+DROP INDEX products_vendor_code_idx;
+
+ALTER TABLE products
+    DROP COLUMN vendor_code,
+    DROP COLUMN min_price,
+    DROP COLUMN max_price;
@@ -0,0 +1,6 @@
+ALTER TABLE products
+    ADD COLUMN vendor_code TEXT,
+    ADD COLUMN min_price INTEGER,
+    ADD COLUMN max_price INTEGER;
+
+CREATE INDEX products_vendor_code_idx ON products (supplier_api_key, vendor_code) WHERE vendor_code IS NOT NULL;
@@ -0,0 +1,52 @@
+ALTER TABLE products
+    DROP CONSTRAINT products_supplier_api_key_fkey,
+    ADD CONSTRAINT products_supplier_api_key_fkey
+    FOREIGN KEY (supplier_api_key) REFERENCES suppliers(api_key) ON DELETE CASCADE;
+
+ALTER TABLE price_update_runs
+    DROP CONSTRAINT price_update_runs_supplier_api_key_fkey,
+    ADD CONSTRAINT price_update_runs_supplier_api_key_fkey
+    FOREIGN KEY (supplier_api_key) REFERENCES suppliers(api_key) ON DELETE CASCADE;
+
+ALTER TABLE planned_price_changes
+    DROP CONSTRAINT planned_price_changes_supplier_api_key_fkey,
+    ADD CONSTRAINT planned_price_changes_supplier_api_key_fkey
+    FOREIGN KEY (supplier_api_key) REFERENCES suppliers(api_key) ON DELETE CASCADE;
+
+ALTER TABLE price_schedules
+    DROP CONSTRAINT price_schedules_supplier_api_key_fkey,
+    ADD CONSTRAINT price_schedules_supplier_api_key_fkey
+    FOREIGN KEY (supplier_api_key) REFERENCES suppliers(api_key) ON DELETE CASCADE;
+
+ALTER TABLE campaigns
+    DROP CONSTRAINT campaigns_supplier_api_key_fkey,
+    ADD CONSTRAINT campaigns_supplier_api_key_fkey
+    FOREIGN KEY (supplier_api_key) REFERENCES suppliers(api_key) ON DELETE CASCADE;
+
+ALTER TABLE freeze_windows
+    DROP CONSTRAINT freeze_windows_supplier_api_key_fkey,
+    ADD CONSTRAINT freeze_windows_supplier_api_key_fkey
+    FOREIGN KEY (supplier_api_key) REFERENCES suppliers(api_key) ON DELETE CASCADE;
+
+ALTER TABLE price_history
+    DROP CONSTRAINT price_history_supplier_api_key_fkey,
+    ADD CONSTRAINT price_history_supplier_api_key_fkey
+    FOREIGN KEY (supplier_api_key) REFERENCES suppliers(api_key) ON DELETE CASCADE;
+
+ALTER TABLE audit_log
+    DROP CONSTRAINT audit_log_supplier_api_key_fkey,
+    ADD CONSTRAINT audit_log_supplier_api_key_fkey
+    FOREIGN KEY (supplier_api_key) REFERENCES suppliers(api_key) ON DELETE CASCADE;
+
+ALTER TABLE price_schedules
+    DROP CONSTRAINT price_schedules_product_fkey,
+    ADD CONSTRAINT price_schedules_product_fkey
+    FOREIGN KEY (supplier_api_key, product_id) REFERENCES products(supplier_api_key, id) ON DELETE CASCADE;
+
+ALTER TABLE campaign_products
+    DROP CONSTRAINT campaign_products_product_fkey,
+    ADD CONSTRAINT campaign_products_product_fkey
+    FOREIGN KEY (supplier_api_key, product_id) REFERENCES products(supplier_api_key, id) ON DELETE CASCADE;
+
+ALTER TABLE suppliers DROP COLUMN api_key_revoked;
+ALTER TABLE suppliers DROP COLUMN disabled;
@@ -0,0 +1,4 @@
+-- suppliers keep their new ids, keys issued so far can't be recovered from their hashes
+ALTER TABLE suppliers ADD COLUMN api_key_revoked BOOLEAN NOT NULL DEFAULT FALSE;
+
+DROP TABLE api_keys;
@@ -0,0 +1,22 @@
+CREATE TABLE api_keys (
+    id BIGSERIAL PRIMARY KEY,
+    supplier_api_key UUID NOT NULL REFERENCES suppliers(api_key) ON DELETE CASCADE ON UPDATE CASCADE,
+    key_hash BYTEA NOT NULL UNIQUE,
+    label TEXT NOT NULL,
+    created_at TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+    last_used_at TIMESTAMPTZ,
+    expires_at TIMESTAMPTZ,
+    revoked_at TIMESTAMPTZ
+);
+
+CREATE INDEX api_keys_supplier_idx ON api_keys (supplier_api_key, created_at DESC);
+
+-- existing keys keep working, hashed the way the app hashes them
+INSERT INTO api_keys (supplier_api_key, key_hash, label, revoked_at)
+SELECT api_key, sha256(uuid_send(api_key)), 'default', CASE WHEN api_key_revoked THEN NOW() END
+FROM suppliers;
+
+ALTER TABLE suppliers DROP COLUMN api_key_revoked;
+
+-- suppliers.api_key is only an id from now on, move it away from the key value so a leaked row can't be used
+UPDATE suppliers SET api_key = uuid_generate_v4();
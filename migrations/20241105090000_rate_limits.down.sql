@@ -0,0 +1,3 @@
+ALTER TABLE plans
+    DROP COLUMN requests_per_minute,
+    DROP COLUMN price_updates_per_minute;
@@ -0,0 +1,3 @@
+ALTER TABLE plans
+    ADD COLUMN requests_per_minute INTEGER NOT NULL DEFAULT 120,
+    ADD COLUMN price_updates_per_minute INTEGER NOT NULL DEFAULT 20;
@@ -0,0 +1 @@
+ALTER TABLE suppliers ADD COLUMN wb_jwt TEXT;
@@ -0,0 +1,9 @@
+-- the app encrypts leftover plaintext tokens right before this migration, refuse to lose any that remain
+DO $$
+BEGIN
+    IF EXISTS (SELECT 1 FROM suppliers WHERE wb_jwt IS NOT NULL) THEN
+        RAISE EXCEPTION 'suppliers.wb_jwt still holds plaintext tokens';
+    END IF;
+END $$;
+
+ALTER TABLE suppliers DROP COLUMN wb_jwt;
@@ -0,0 +1,4 @@
+DROP INDEX planned_price_changes_supplier_idx;
+CREATE INDEX planned_price_changes_supplier_idx ON planned_price_changes (supplier_api_key, created_at DESC);
+
+ALTER TABLE planned_price_changes DROP COLUMN frozen;
@@ -0,0 +1,9 @@
+ALTER TABLE planned_price_changes ADD COLUMN frozen BOOLEAN NOT NULL DEFAULT FALSE;
+
+UPDATE planned_price_changes c
+SET frozen = TRUE
+FROM price_update_runs r
+WHERE c.run_id = r.id AND r.frozen;
+
+DROP INDEX planned_price_changes_supplier_idx;
+CREATE INDEX planned_price_changes_supplier_idx ON planned_price_changes (supplier_api_key, frozen, created_at DESC);
@@ -0,0 +1 @@
+ALTER TABLE audit_log ADD COLUMN api_key_id BIGINT REFERENCES api_keys(id) ON DELETE SET NULL;
@@ -0,0 +1,66 @@
+use std::convert::Infallible;
+use std::sync::Arc;
+use axum::async_trait;
+use axum::extract::FromRequestParts;
+use axum::http::request::Parts;
+use serde_json::Value;
+use tracing::error;
+use uuid::Uuid;
+use crate::api::client_ip::client_ip;
+use crate::api::middlewares::{Admin, ApiKeyId};
+use crate::db::audit::NewAuditEntry;
+use crate::db::supplier::Supplier;
+use crate::state::AppState;
+
+pub struct Audit {
+    state: Arc<AppState>,
+    supplier_api_key: Option<Uuid>,
+    api_key_id: Option<i64>,
+    actor: String,
+    client_ip: Option<String>,
+}
+
+#[async_trait]
+impl FromRequestParts<Arc<AppState>> for Audit {
+    type Rejection = Infallible;
+
+    async fn from_request_parts(parts: &mut Parts, state: &Arc<AppState>) -> Result<Self, Self::Rejection> {
+        let supplier = parts.extensions.get::<Supplier>();
+
+        Ok(Audit {
+            state: state.clone(),
+            supplier_api_key: supplier.map(|supplier| supplier.api_key),
+            api_key_id: parts.extensions.get::<ApiKeyId>().map(|ApiKeyId(id)| *id),
+            actor: match (supplier, parts.extensions.get::<Admin>()) {
+                (Some(_), _) => "supplier".to_string(),
+                (None, Some(Admin(name))) => format!("admin:{}", name),
+                (None, None) => "admin".to_string(),
+            },
+            client_ip: client_ip(&parts.extensions, &parts.headers).map(|ip| ip.to_string()),
+        })
+    }
+}
+
+impl Audit {
+    pub fn for_supplier(mut self, api_key: Uuid) -> Self {
+        self.supplier_api_key = Some(api_key);
+        self
+    }
+
+    pub async fn record(&self, action: &str, target: Option<String>, old_value: Option<Value>, new_value: Option<Value>) {
+        let entry = NewAuditEntry {
+            supplier_api_key: self.supplier_api_key,
+            api_key_id: self.api_key_id,
+            actor: self.actor.clone(),
+            action: action.to_string(),
+            target,
+            old_value,
+            new_value,
+            client_ip: self.client_ip.clone(),
+        };
+
+        if let Err(err) = self.state.add_audit_entry(&entry).await {
+            error!("{}", err);
+        }
+    }
+}
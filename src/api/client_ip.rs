@@ -0,0 +1,102 @@
+use std::net::{IpAddr, SocketAddr};
+use std::sync::Arc;
+use axum::extract::ConnectInfo;
+use axum::http::{Extensions, HeaderMap};
+use crate::utils;
+
+/// Proxies allowed to tell the client address in `X-Forwarded-For`.
+#[derive(Debug, Default)]
+pub struct TrustedProxies(Vec<IpAddr>);
+
+impl TrustedProxies {
+    // TRUSTED_PROXIES="10.0.0.2,10.0.0.3", unset when clients connect directly
+    pub fn from_env() -> Result<Self, String> {
+        let Ok(proxies) = utils::get_env_var("TRUSTED_PROXIES") else {
+            return Ok(Self::default());
+        };
+
+        proxies.split(',')
+            .map(str::trim)
+            .filter(|proxy| !proxy.is_empty())
+            .map(|proxy| proxy.parse().map_err(|_| format!("TRUSTED_PROXIES entry {} must be an ip address", proxy)))
+            .collect::<Result<_, _>>()
+            .map(Self)
+    }
+
+    /// Walks `X-Forwarded-For` from the right while the hops are trusted proxies,
+    /// entries further left could have been written by the client itself.
+    fn resolve(&self, peer: IpAddr, headers: &HeaderMap) -> IpAddr {
+        if !self.0.contains(&peer) {
+            return peer;
+        }
+
+        let forwarded = headers.get_all("X-Forwarded-For")
+            .iter()
+            .filter_map(|value| value.to_str().ok())
+            .flat_map(|value| value.split(','))
+            .map(str::trim)
+            .collect::<Vec<_>>();
+        let mut client = peer;
+        for hop in forwarded.into_iter().rev() {
+            match hop.parse() {
+                Ok(ip) => client = ip,
+                Err(_) => break,
+            }
+            if !self.0.contains(&client) {
+                break;
+            }
+        }
+
+        client
+    }
+}
+
+/// Address of the client behind any trusted proxies. `None` only when the server runs without
+/// connect info, in which case rate limits and admin lockouts are shared by all such requests.
+pub fn client_ip(extensions: &Extensions, headers: &HeaderMap) -> Option<IpAddr> {
+    let ConnectInfo(peer) = extensions.get::<ConnectInfo<SocketAddr>>()?;
+
+    Some(match extensions.get::<Arc<TrustedProxies>>() {
+        Some(proxies) => proxies.resolve(peer.ip(), headers),
+        None => peer.ip(),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use axum::http::HeaderValue;
+    use super::*;
+
+    fn ip(ip: &str) -> IpAddr {
+        ip.parse().unwrap()
+    }
+
+    fn forwarded(value: &str) -> HeaderMap {
+        let mut headers = HeaderMap::new();
+        headers.insert("X-Forwarded-For", HeaderValue::from_str(value).unwrap());
+        headers
+    }
+
+    #[test]
+    fn ignores_header_from_untrusted_peer() {
+        let proxies = TrustedProxies(vec![ip("10.0.0.2")]);
+
+        assert_eq!(proxies.resolve(ip("1.1.1.1"), &forwarded("2.2.2.2")), ip("1.1.1.1"));
+    }
+
+    #[test]
+    fn takes_first_untrusted_hop_from_the_right() {
+        let proxies = TrustedProxies(vec![ip("10.0.0.2"), ip("10.0.0.3")]);
+
+        let client = proxies.resolve(ip("10.0.0.2"), &forwarded("6.6.6.6, 1.1.1.1, 10.0.0.3"));
+
+        assert_eq!(client, ip("1.1.1.1"));
+    }
+
+    #[test]
+    fn stops_at_malformed_hop() {
+        let proxies = TrustedProxies(vec![ip("10.0.0.2")]);
+
+        assert_eq!(proxies.resolve(ip("10.0.0.2"), &forwarded("1.1.1.1, garbage")), ip("10.0.0.2"));
+    }
+}
@@ -6,45 +6,123 @@ use axum::{
 use serde_json::json;
 use thiserror::Error;
 use tracing::error;
+use crate::api::validation::Violation;
+use crate::error::Error;
 
 #[derive(Error, Debug)]
 pub enum AppError {
-    // #[error("Database error: {0}")]
-    // DbError(#[from] sqlx::Error),
+    #[error("Invalid input: {0}")]
+    InvalidInput(String),
 
-    // #[error("Invalid input: {0}")]
-    // InvalidInput(String),
+    #[error("Validation failed: {}", join(.0))]
+    Validation(Vec<Violation>),
+
+    #[error("Unauthorized")]
+    Unauthorized,
 
     #[error("No permission: {0}")]
     NoPermission(String),
 
-    // #[error("Resource not found")]
-    // NotFound,
+    #[error("Not found: {0}")]
+    NotFound(String),
+
+    #[error("Conflict: {0}")]
+    Conflict(String),
+
+    #[error("Plan limit: {0}")]
+    PlanLimit(String),
+
+    #[error("Rate limited: {0}")]
+    RateLimited(String),
+
+    #[error("Upstream error: {0}")]
+    Upstream(String),
 
     #[error("Internal server error")]
     InternalServerError,
 }
 
 impl AppError {
-    pub fn unexpected(text: &str) -> AppError {
-        error!("{}", text);
+    pub fn unexpected(err: &dyn std::fmt::Display) -> AppError {
+        error!("{}", err);
         AppError::InternalServerError
     }
+
+    pub fn invalid(field: &str, message: &str) -> AppError {
+        AppError::Validation(vec![Violation { field: field.to_string(), message: message.to_string() }])
+    }
+
+    pub fn code(&self) -> &'static str {
+        match self {
+            AppError::InvalidInput(_) => "invalid_input",
+            AppError::Validation(_) => "validation_failed",
+            AppError::Unauthorized => "unauthorized",
+            AppError::NoPermission(_) => "no_permission",
+            AppError::NotFound(_) => "not_found",
+            AppError::Conflict(_) => "conflict",
+            AppError::PlanLimit(_) => "plan_limit",
+            AppError::RateLimited(_) => "rate_limited",
+            AppError::Upstream(_) => "upstream_error",
+            AppError::InternalServerError => "internal_error",
+        }
+    }
+
+    fn status(&self) -> StatusCode {
+        match self {
+            AppError::InvalidInput(_) => StatusCode::BAD_REQUEST,
+            AppError::Validation(_) => StatusCode::UNPROCESSABLE_ENTITY,
+            AppError::Unauthorized => StatusCode::UNAUTHORIZED,
+            AppError::NoPermission(_) => StatusCode::FORBIDDEN,
+            AppError::NotFound(_) => StatusCode::NOT_FOUND,
+            AppError::Conflict(_) | AppError::PlanLimit(_) => StatusCode::CONFLICT,
+            AppError::RateLimited(_) => StatusCode::TOO_MANY_REQUESTS,
+            AppError::Upstream(_) => StatusCode::BAD_GATEWAY,
+            AppError::InternalServerError => StatusCode::INTERNAL_SERVER_ERROR,
+        }
+    }
+}
+
+impl From<Error> for AppError {
+    fn from(err: Error) -> Self {
+        match err {
+            Error::WbJwtExpired | Error::WbUnauthorized(_) => AppError::NoPermission(err.to_string()),
+            Error::WbRateLimited => AppError::RateLimited(err.to_string()),
+            Error::Http { .. } | Error::WbStatus(_) | Error::MissingSupplierId => AppError::Upstream(err.to_string()),
+            Error::InvalidApiKey => AppError::Unauthorized,
+            Error::GoodsLimit { .. } => AppError::PlanLimit(err.to_string()),
+            err => AppError::unexpected(&err),
+        }
+    }
 }
 
 impl IntoResponse for AppError {
     fn into_response(self) -> Response {
-        let (status, error_message) = match self {
-            // AppError::InvalidInput(msg) => (StatusCode::BAD_REQUEST, msg),
-            AppError::NoPermission(msg) => (StatusCode::FORBIDDEN, msg),
-            // AppError::NotFound => (StatusCode::NOT_FOUND, "Not found".to_string()),
-            AppError::InternalServerError => (StatusCode::INTERNAL_SERVER_ERROR, "Internal server error".to_string()),
+        let (status, code) = (self.status(), self.code());
+        let (error_message, violations) = match self {
+            AppError::Validation(violations) => (join(&violations), Some(violations)),
+            AppError::InvalidInput(msg)
+            | AppError::NoPermission(msg)
+            | AppError::NotFound(msg)
+            | AppError::Conflict(msg)
+            | AppError::PlanLimit(msg)
+            | AppError::RateLimited(msg)
+            | AppError::Upstream(msg) => (msg, None),
+            AppError::Unauthorized => ("Invalid api key".to_string(), None),
+            AppError::InternalServerError => ("Internal server error".to_string(), None),
         };
 
-        let body = Json(json!({
+        let mut body = json!({
             "error": error_message,
-        }));
+            "code": code,
+        });
+        if let Some(violations) = violations {
+            body["violations"] = json!(violations);
+        }
 
-        (status, body).into_response()
+        (status, Json(body)).into_response()
     }
-}
\ No newline at end of file
+}
+
+fn join(violations: &[Violation]) -> String {
+    violations.iter().map(Violation::to_string).collect::<Vec<_>>().join("; ")
+}
@@ -0,0 +1,135 @@
+use std::io::{Seek, SeekFrom};
+use axum::body::Body;
+use axum::http::header;
+use axum::response::{IntoResponse, Response};
+use futures::{stream, Stream, StreamExt};
+use rust_xlsxwriter::Workbook;
+use serde::Deserialize;
+use tokio::sync::mpsc;
+use tokio_util::io::ReaderStream;
+use crate::api::error::AppError;
+use crate::utils;
+
+pub const PAGE_SIZE: usize = 1000;
+
+pub enum Cell {
+    Number(Option<f64>),
+    Text(Option<String>),
+}
+
+pub type Rows = Vec<Vec<Cell>>;
+
+#[derive(Deserialize, Default, Clone, Copy)]
+#[serde(rename_all = "lowercase")]
+pub enum ExportFormat {
+    #[default]
+    Csv,
+    Xlsx,
+}
+
+#[derive(Deserialize)]
+pub struct ExportQuery {
+    #[serde(default)]
+    pub format: ExportFormat,
+}
+
+/// Sends pages to the client as they come from the db. XLSX can't be written
+/// incrementally over the wire, so rows go to a temporary file first.
+pub async fn respond<S>(format: ExportFormat, name: &str, header: &'static [&'static str], pages: S) -> Result<Response, AppError>
+where
+    S: Stream<Item = Result<Rows, String>> + Send + Unpin + 'static,
+{
+    let (content_type, body) = match format {
+        ExportFormat::Csv => ("text/csv; charset=utf-8", csv_body(header, pages)),
+        ExportFormat::Xlsx => (
+            "application/vnd.openxmlformats-officedocument.spreadsheetml.sheet",
+            xlsx_body(header, pages).await.map_err(|err| AppError::unexpected(&err))?,
+        ),
+    };
+    let extension = match format {
+        ExportFormat::Csv => "csv",
+        ExportFormat::Xlsx => "xlsx",
+    };
+
+    Ok((
+        [
+            (header::CONTENT_TYPE, content_type.to_string()),
+            (header::CONTENT_DISPOSITION, format!("attachment; filename=\"{}.{}\"", name, extension)),
+        ],
+        body,
+    ).into_response())
+}
+
+fn csv_body<S>(header: &'static [&'static str], pages: S) -> Body
+where
+    S: Stream<Item = Result<Rows, String>> + Send + 'static,
+{
+    let header = stream::once(async move { write_csv(header, &[]) });
+    let rows = pages.map(|page| page.and_then(|rows| write_csv(&[], &rows)));
+
+    Body::from_stream(header.chain(rows))
+}
+
+fn write_csv(header: &[&str], rows: &[Vec<Cell>]) -> Result<Vec<u8>, String> {
+    let mut writer = csv::WriterBuilder::new().flexible(true).from_writer(Vec::new());
+    if !header.is_empty() {
+        writer.write_record(header).map_err(|err| utils::make_err(Box::new(err), "write csv"))?;
+    }
+    for row in rows {
+        let record = row.iter().map(|cell| match cell {
+            Cell::Number(number) => number.map(|number| number.to_string()).unwrap_or_default(),
+            Cell::Text(text) => text.clone().unwrap_or_default(),
+        });
+        writer.write_record(record).map_err(|err| utils::make_err(Box::new(err), "write csv"))?;
+    }
+
+    writer.into_inner().map_err(|err| utils::make_err(Box::new(err), "flush csv"))
+}
+
+async fn xlsx_body<S>(header: &'static [&'static str], mut pages: S) -> Result<Body, String>
+where
+    S: Stream<Item = Result<Rows, String>> + Unpin,
+{
+    let (tx, mut rx) = mpsc::channel::<Rows>(2);
+    let writer = tokio::task::spawn_blocking(move || {
+        let mut workbook = Workbook::new();
+        let worksheet = workbook.add_worksheet_with_constant_memory();
+        let write_err = |err| utils::make_err(Box::new(err), "write xlsx");
+
+        for (column, title) in header.iter().enumerate() {
+            worksheet.write_string(0, column as u16, *title).map_err(write_err)?;
+        }
+        let mut row_number = 1;
+        while let Some(rows) = rx.blocking_recv() {
+            for row in rows {
+                for (column, cell) in row.into_iter().enumerate() {
+                    match cell {
+                        Cell::Number(Some(number)) => worksheet.write_number(row_number, column as u16, number),
+                        Cell::Text(Some(text)) => worksheet.write_string(row_number, column as u16, text),
+                        Cell::Number(None) | Cell::Text(None) => continue,
+                    }.map_err(write_err)?;
+                }
+                row_number += 1;
+            }
+        }
+
+        let mut file = tempfile::tempfile().map_err(|err| utils::make_err(Box::new(err), "create temporary file"))?;
+        workbook.save_to_writer(&mut file).map_err(write_err)?;
+        file.seek(SeekFrom::Start(0)).map_err(|err| utils::make_err(Box::new(err), "rewind xlsx"))?;
+
+        Ok::<_, String>(file)
+    });
+
+    while let Some(rows) = pages.next().await {
+        if tx.send(rows?).await.is_err() {
+            break;
+        }
+    }
+    drop(tx);
+
+    let file = writer
+        .await
+        .map_err(|err| utils::make_err(Box::new(err), "join xlsx writer"))??;
+
+    Ok(Body::from_stream(ReaderStream::new(tokio::fs::File::from_std(file))))
+}
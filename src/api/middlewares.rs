@@ -1,41 +1,183 @@
 use std::sync::Arc;
-use axum::extract::{Request, State};
-use axum::http::StatusCode;
+use axum::Extension;
+use axum::extract::{OriginalUri, Request, State};
+use axum::http::{HeaderMap, HeaderValue};
 use axum::middleware::Next;
-use axum::response::Response;
+use axum::response::{IntoResponse, Response};
+use tracing::{info, warn};
 use uuid::Uuid;
+use crate::api::client_ip::client_ip;
+use crate::api::error::AppError;
+use crate::db::plan::Plan;
+use crate::db::supplier::Supplier;
+use crate::error::Error;
+use crate::rate_limit::Quota;
 use crate::state::AppState;
-use crate::utils;
-
-pub async fn get_super(request: Request, next: Next) -> Result<Response, StatusCode> {
-    if let Some(api_key_header) = request.headers().get("Authorization") {
-        if let Ok(api_key) = api_key_header.to_str() {
-            if let Ok(expected_api_key) = utils::get_env_var("SUPER_API_KEY") {
-                if api_key == expected_api_key {
-                    return Ok(next.run(request).await);
-                }
-            }
-        }
+
+// requests per minute from one ip to the routes that don't take a supplier key
+const IP_REQUESTS_PER_MINUTE: u32 = 60;
+// requests per minute from one ip with a missing or unknown supplier key
+const AUTH_FAILURES_PER_MINUTE: u32 = 20;
+
+/// Name of the admin key a request was authenticated with.
+#[derive(Clone)]
+pub struct Admin(pub String);
+
+/// Id of the supplier key a request was authenticated with.
+#[derive(Clone)]
+pub struct ApiKeyId(pub i64);
+
+pub async fn get_super(
+    State(state): State<Arc<AppState>>,
+    mut request: Request,
+    next: Next,
+) -> Result<Response, AppError> {
+    let client = client_key(&request);
+    if let Some(wait) = state.admin_lockout(&client) {
+        return Err(AppError::RateLimited(format!("Too many failed admin attempts, retry in {}s", wait.as_secs().max(1))));
     }
 
-    Err(StatusCode::UNAUTHORIZED)?
+    let path = request.extensions()
+        .get::<OriginalUri>()
+        .map(|OriginalUri(uri)| uri.path().to_string())
+        .unwrap_or_else(|| request.uri().path().to_string());
+    let admin = request.headers()
+        .get("Authorization")
+        .and_then(|header| header.to_str().ok())
+        .and_then(|key| state.verify_admin_key(key));
+    match admin {
+        Some(admin) => {
+            info!("Admin {} {} {}", admin, request.method(), path);
+            let admin = Admin(admin.to_string());
+            request.extensions_mut().insert(admin);
+            Ok(next.run(request).await)
+        }
+        None => {
+            warn!("Rejected admin key from {} for {} {}", client, request.method(), path);
+            state.register_admin_failure(&client);
+            Err(AppError::Unauthorized)
+        }
+    }
 }
 
 pub async fn get_auth(
     State(state): State<Arc<AppState>>,
     mut request: Request,
     next: Next,
-) -> Result<Response, StatusCode> {
-    if let Some(api_key_header) = request.headers().get("Authorization") {
-        if let Ok(api_key) = api_key_header.to_str() {
-            if let Ok(api_key) = Uuid::parse_str(api_key) {
-                if let Ok(supplier) = state.get_supplier(&api_key).await {
-                    request.extensions_mut().insert(supplier);
-                    return Ok(next.run(request).await);
-                }
+) -> Result<Response, AppError> {
+    let api_key = request.headers()
+        .get("Authorization")
+        .and_then(|header| header.to_str().ok())
+        .and_then(|key| Uuid::parse_str(key).ok());
+    let supplier = match api_key {
+        Some(api_key) => state.authenticate(&api_key).await,
+        None => Err(Error::InvalidApiKey),
+    };
+
+    match supplier {
+        Ok((supplier, key_id)) => {
+            if supplier.disabled {
+                return Err(AppError::NoPermission("Supplier is disabled".to_string()));
             }
+            request.extensions_mut().insert(supplier);
+            request.extensions_mut().insert(ApiKeyId(key_id));
+            Ok(next.run(request).await)
+        }
+        Err(Error::InvalidApiKey) => Ok(failed_auth(&state, &request)),
+        // a db failure while looking the key up is a 500, not a failed attempt
+        Err(err) => Err(err.into()),
+    }
+}
+
+/// Missing, unknown, revoked and expired keys count against the client ip, so guessing keys
+/// is throttled without touching the budget of requests that authenticate.
+fn failed_auth(state: &AppState, request: &Request) -> Response {
+    let key = format!("auth_fail:{}", client_key(request));
+    match state.hit_rate_limit(&key, AUTH_FAILURES_PER_MINUTE) {
+        Ok(_) => AppError::Unauthorized.into_response(),
+        Err(quota) => too_many_requests(&quota),
+    }
+}
+
+pub async fn limit_by_ip(
+    State(state): State<Arc<AppState>>,
+    request: Request,
+    next: Next,
+) -> Response {
+    let key = format!("ip:{}", client_key(&request));
+    rate_limited(&state, &key, IP_REQUESTS_PER_MINUTE, request, next).await
+}
+
+pub async fn limit_by_supplier(
+    State(state): State<Arc<AppState>>,
+    Extension(supplier): Extension<Supplier>,
+    request: Request,
+    next: Next,
+) -> Result<Response, AppError> {
+    let plan = supplier_plan(&state, &supplier).await?;
+    let key = format!("supplier:{}", supplier.api_key);
+
+    Ok(rate_limited(&state, &key, plan.requests_per_minute.max(0) as u32, request, next).await)
+}
+
+pub async fn limit_price_updates(
+    State(state): State<Arc<AppState>>,
+    Extension(supplier): Extension<Supplier>,
+    request: Request,
+    next: Next,
+) -> Result<Response, AppError> {
+    let plan = supplier_plan(&state, &supplier).await?;
+    let key = format!("update_price:{}", supplier.api_key);
+
+    Ok(rate_limited(&state, &key, plan.price_updates_per_minute.max(0) as u32, request, next).await)
+}
+
+async fn supplier_plan(state: &AppState, supplier: &Supplier) -> Result<Plan, AppError> {
+    state.get_plan(&supplier.plan)
+        .await?
+        .ok_or_else(|| AppError::unexpected(&format!("Unknown plan {} of supplier {}", supplier.plan, supplier)))
+}
+
+async fn rate_limited(state: &AppState, key: &str, per_minute: u32, request: Request, next: Next) -> Response {
+    match state.hit_rate_limit(key, per_minute) {
+        Ok(quota) => {
+            let mut response = next.run(request).await;
+            set_rate_limit_headers(response.headers_mut(), &quota);
+            response
         }
+        Err(quota) => too_many_requests(&quota),
+    }
+}
+
+fn too_many_requests(quota: &Quota) -> Response {
+    let mut response = AppError::RateLimited(format!("Limit of {} requests per minute exceeded", quota.limit))
+        .into_response();
+    set_rate_limit_headers(response.headers_mut(), quota);
+    response.headers_mut().insert("Retry-After", HeaderValue::from(reset_secs(quota)));
+    response
+}
+
+// with nested limits the response reports the one closest to running out
+fn set_rate_limit_headers(headers: &mut HeaderMap, quota: &Quota) {
+    let tighter = headers
+        .get("RateLimit-Remaining")
+        .and_then(|value| value.to_str().ok()?.parse::<u32>().ok())
+        .is_some_and(|remaining| remaining <= quota.remaining);
+    if tighter {
+        return;
     }
 
-    Err(StatusCode::UNAUTHORIZED)?
-}
\ No newline at end of file
+    headers.insert("RateLimit-Limit", HeaderValue::from(quota.limit));
+    headers.insert("RateLimit-Remaining", HeaderValue::from(quota.remaining));
+    headers.insert("RateLimit-Reset", HeaderValue::from(reset_secs(quota)));
+}
+
+fn reset_secs(quota: &Quota) -> u64 {
+    quota.reset.as_secs().max(1)
+}
+
+fn client_key(request: &Request) -> String {
+    client_ip(request.extensions(), request.headers())
+        .map(|ip| ip.to_string())
+        .unwrap_or_else(|| "unknown".to_string())
+}
@@ -2,15 +2,24 @@ mod router;
 mod middlewares;
 mod ping;
 mod error;
+mod audit;
+mod export;
+mod validation;
+mod client_ip;
 
+use std::net::SocketAddr;
 use std::sync::Arc;
+use axum::Extension;
 use tower_http::cors::{Any, CorsLayer};
 use tracing::info;
+use crate::api::client_ip::TrustedProxies;
 use crate::state::AppState;
 use crate::utils;
 
 pub async fn run(app_state: Arc<AppState>) -> Result<(), String> {
-    let mut router = router::get_router(app_state);
+    let trusted_proxies = TrustedProxies::from_env()?;
+    let mut router = router::get_router(app_state)
+        .layer(Extension(Arc::new(trusted_proxies)));
 
     if Some("1") == utils::get_env_or("DEBUG", "0".to_string()).ok().as_deref() {
         info!("will be allowed any cors");
@@ -29,7 +38,7 @@ pub async fn run(app_state: Arc<AppState>) -> Result<(), String> {
         .await
         .expect("Failed init listener");
 
-    axum::serve(listener, router.into_make_service()).await.expect("Failed start serving");
+    axum::serve(listener, router.into_make_service_with_connect_info::<SocketAddr>()).await.expect("Failed start serving");
 
     Ok(())
 }
\ No newline at end of file
@@ -1,69 +1,330 @@
+use std::collections::{HashMap, HashSet};
 use std::sync::Arc;
 use axum::{Extension, Json, middleware, Router};
-use axum::extract::{Path, State};
+use axum::extract::{DefaultBodyLimit, Multipart, State};
 use axum::http::StatusCode;
-use axum::response::IntoResponse;
-use axum::routing::{delete, get, post};
-use serde::{Deserialize, Serialize};
+use axum::response::{IntoResponse, Response};
+use axum::routing::{delete, get, post, put};
+use chrono::{DateTime, Utc};
+use chrono_tz::Tz;
+use futures::stream;
+use serde::{Deserialize, Deserializer, Serialize};
+use serde_json::json;
+use uuid::Uuid;
+use tracing::error;
+use crate::api::audit::Audit;
 use crate::api::error::AppError;
-use crate::api::middlewares::{get_auth, get_super};
+use crate::api::export::{self, Cell, ExportQuery};
+use crate::api::middlewares::{get_auth, get_super, limit_by_ip, limit_by_supplier, limit_price_updates};
 use crate::api::ping::ping;
-use crate::db::product::Product;
-use crate::db::supplier::Supplier;
+use crate::api::validation::{JsonInput, PathInput, QueryInput, Validate, ValidJson, ValidPath, ValidQuery, Violations};
+use crate::db::product::{GoodSettings, GoodStatus, GoodsFilter, Product};
+use crate::db::run::Run;
+use crate::db::api_key::ApiKey;
+use crate::db::audit::AuditEntry;
+use crate::db::plan::Plan;
+use crate::db::planned_change::PlannedChange;
+use crate::db::schedule::PriceWindow;
+use crate::db::campaign::Campaign;
+use crate::db::freeze::FreezeWindow;
+use crate::db::price_history::{ObservedPrice, UploadedPrice};
+use crate::db::supplier::{Supplier, SupplierFilter};
+use crate::error::Error;
 use crate::state::AppState;
-use crate::utils;
+use crate::{calc, import, update, utils};
+use crate::import::RowError;
 use crate::wb::calculate_and_set_price;
 
+const IMPORT_MAX_BYTES: usize = 20 * 1024 * 1024;
+const PATCH_ATTEMPTS: usize = 3;
+
 pub fn get_router(app_state: Arc<AppState>) -> Router {
     let protected_routes = Router::new()
         .route("/state", get(get_state))
         .route("/set_wb_jwt", post(set_wb_jwt))
-        .route("/update_price", post(update_price))
-        .route("/goods/:good_id", delete(delete_good))
+        .route("/update_price",
+               post(update_price)
+                   .layer(middleware::from_fn_with_state(app_state.clone(), limit_price_updates)))
+        .route("/goods", get(get_goods))
+        .route("/goods/export", get(export_goods))
+        .route("/goods/bulk", post(bulk_upsert_goods))
+        .route("/goods/import", post(import_goods).layer(DefaultBodyLimit::max(IMPORT_MAX_BYTES)))
+        .route("/goods/:good_id", put(put_good).patch(patch_good).delete(delete_good))
+        .route("/goods/:good_id/pause", post(pause_good))
+        .route("/goods/:good_id/resume", post(resume_good))
+        .route("/goods/:good_id/schedule", get(get_schedule).put(set_schedule))
+        .route("/goods/:good_id/history", get(get_history))
+        .route("/history/export", get(export_history))
+        .route("/goods/pause", post(pause_goods))
+        .route("/goods/resume", post(resume_goods))
+        .route("/campaigns", get(get_campaigns).post(create_campaign))
+        .route("/campaigns/:campaign_id", get(get_campaign).delete(delete_campaign))
+        .route("/freezes", get(get_freezes).post(create_freeze))
+        .route("/freezes/changes", get(get_frozen_changes))
+        .route("/freezes/:freeze_id", delete(delete_freeze))
+        .route("/api_keys", get(get_api_keys).post(create_supplier_api_key))
+        .route("/api_keys/:key_id", delete(revoke_supplier_api_key))
+        .route("/pause", post(pause_supplier))
+        .route("/resume", post(resume_supplier))
+        .route("/runs", get(get_runs))
+        .route("/set_dry_run", post(set_dry_run))
+        .route("/set_timezone", post(set_timezone))
+        .route("/sync", post(sync))
+        .route("/dry_run/changes", get(get_planned_changes))
+        .route("/audit", get(get_audit_log))
+        .layer(middleware::from_fn_with_state(app_state.clone(), limit_by_supplier))
         .layer(middleware::from_fn_with_state(app_state.clone(), get_auth));
 
-    Router::new()
+    let admin_routes = Router::new()
+        .route("/suppliers", get(admin_get_suppliers))
+        .route("/suppliers/:api_key", get(admin_get_supplier).delete(admin_delete_supplier))
+        .route("/suppliers/:api_key/goods", get(admin_get_goods))
+        .route("/suppliers/:api_key/runs", get(admin_get_runs))
+        .route("/suppliers/:api_key/disable", post(admin_disable_supplier))
+        .route("/suppliers/:api_key/enable", post(admin_enable_supplier))
+        .route("/suppliers/:api_key/revoke_api_key", post(admin_revoke_api_key))
+        .route("/suppliers/:api_key/reissue_api_key", post(admin_reissue_api_key))
+        .route("/suppliers/:api_key/plan", put(admin_set_plan))
+        .route("/suppliers/:api_key/sync", post(admin_sync))
+        .layer(middleware::from_fn_with_state(app_state.clone(), get_super));
+
+    let public_routes = Router::new()
         .route("/ping", get(ping))
         .route("/create_api_key",
                post(create_api_key)
                    .layer(middleware::from_fn_with_state(app_state.clone(), get_super)))
+        .route("/set_plan",
+               post(set_plan)
+                   .layer(middleware::from_fn_with_state(app_state.clone(), get_super)))
+        .nest("/admin", admin_routes)
+        .layer(middleware::from_fn_with_state(app_state.clone(), limit_by_ip));
+
+    Router::new()
+        .merge(public_routes)
         .nest("/", protected_routes)
         .with_state(app_state)
 }
 
+#[derive(Deserialize)]
+struct GoodPath {
+    good_id: i32,
+}
+
+impl Validate for GoodPath {
+    fn validate(&self, violations: &mut Violations) {
+        violations.check(self.good_id > 0, "good_id", "must be positive");
+    }
+}
+
+#[derive(Deserialize)]
+struct CampaignPath {
+    campaign_id: i64,
+}
+
+impl Validate for CampaignPath {
+    fn validate(&self, violations: &mut Violations) {
+        violations.check(self.campaign_id > 0, "campaign_id", "must be positive");
+    }
+}
+
+#[derive(Deserialize)]
+struct FreezePath {
+    freeze_id: i64,
+}
+
+impl Validate for FreezePath {
+    fn validate(&self, violations: &mut Violations) {
+        violations.check(self.freeze_id > 0, "freeze_id", "must be positive");
+    }
+}
+
+#[derive(Deserialize)]
+struct KeyPath {
+    key_id: i64,
+}
+
+impl Validate for KeyPath {
+    fn validate(&self, violations: &mut Violations) {
+        violations.check(self.key_id > 0, "key_id", "must be positive");
+    }
+}
+
 #[derive(Serialize)]
 struct PriceSet {
     products: Vec<Product>,
 }
 
+#[derive(Deserialize)]
+struct UpdatePrice {
+    id: i32,
+    price: i32,
+    #[serde(default)]
+    force: bool,
+}
+
+impl Validate for UpdatePrice {
+    fn validate(&self, violations: &mut Violations) {
+        violations.check(self.id > 0, "id", "must be positive");
+        violations.check(self.price > 0, "price", "must be positive");
+    }
+}
+
 async fn update_price(
     State(state): State<Arc<AppState>>,
     Extension(supplier): Extension<Supplier>,
-    Json(input): Json<Product>,
+    audit: Audit,
+    ValidJson(input): ValidJson<UpdatePrice>,
 ) -> Result<impl IntoResponse, AppError> {
     let wb_jwt = supplier.wb_jwt
+        .clone()
         .ok_or_else(|| AppError::NoPermission("Need set JWT".to_string()))?;
 
-    match calculate_and_set_price(supplier.wb_id, &wb_jwt, vec![Product::new(input.id, input.price)]).await {
-        Ok((supplier_id, products)) => {
-            if let Some(supplier_id) = supplier_id {
+    if supplier.needs_reauth {
+        return Err(AppError::NoPermission("WB rejected JWT, need set new one".to_string()));
+    }
+
+    if !input.force {
+        let freeze = state.get_active_freeze(&supplier.api_key, Utc::now())
+            .await
+            .map_err(|err| AppError::unexpected(&err))?;
+        if let Some(freeze) = freeze {
+            return Err(AppError::Conflict(format!(
+                "Prices are frozen until {}{}, pass force to change anyway",
+                freeze.ends_at,
+                freeze.reason.map(|reason| format!(" ({})", reason)).unwrap_or_default(),
+            )));
+        }
+    }
+
+    let force = input.force;
+    let input = Product::new(input.id, input.price);
+    let old = state.get_good_settings(&supplier.api_key, input.id)
+        .await
+        .map_err(|err| AppError::unexpected(&err))?;
+    if let Some(good) = &old {
+        if !good.active {
+            return Err(AppError::Conflict(format!("Good {} is paused, resume it first", input.id)));
+        }
+        let mut violations = Violations::default();
+        check_good(&mut violations, Some(input.price), good.min_price, good.max_price, None);
+        violations.into_result()?;
+    }
+    let plan = get_plan(&state, &supplier).await?;
+    if old.is_none() {
+        // checked before calling WB as well, storing the good enforces it again under a lock
+        let total = state.count_by_apikey(&supplier.api_key)
+            .await
+            .map_err(|err| AppError::unexpected(&err))? + 1;
+        if total > plan.max_products as i64 {
+            return Err(Error::GoodsLimit { plan: plan.id, max: plan.max_products, total }.into());
+        }
+    }
+    // an active schedule or campaign still wins, the same way the next updater run would decide
+    let goods = update::target_prices(&state, &supplier, vec![input.clone()])
+        .await
+        .map_err(|err| AppError::unexpected(&err))?;
+    match calculate_and_set_price(supplier.wb_id, &wb_jwt, goods, supplier.dry_run).await {
+        Ok(update) => {
+            if update.missing.contains(&input.id) {
+                return Err(AppError::invalid("id", "is not a product on WB"));
+            }
+            if let Some(supplier_id) = update.supplier_id {
                 state.set_wb_id(&supplier.api_key, supplier_id)
                     .await
                     .map_err(|err| AppError::unexpected(&err))?;
             }
-            let _ = state.add_goods(&supplier.api_key, &vec![input]).await;
-            Ok(Json(PriceSet { products }))
+            // WB already has the price, losing a history row must not make the client retry it
+            if let Err(err) = state.record_price_history(&supplier.api_key, &update).await {
+                error!("{}", err);
+            }
+            if supplier.dry_run {
+                state.add_planned_changes(&supplier.api_key, None, false, &update.changes)
+                    .await
+                    .map_err(|err| AppError::unexpected(&err))?;
+            }
+            state.add_goods(&supplier.api_key, std::slice::from_ref(&input), &plan).await?;
+            audit.record(
+                "update_price",
+                Some(input.id.to_string()),
+                old.map(|good| json!({ "price": good.price })),
+                Some(json!({ "price": input.price, "force": force })),
+            ).await;
+            Ok(Json(PriceSet { products: update.products() }))
+        }
+        Err(err) => {
+            if err.is_unauthorized() {
+                update::register_auth_failure(&state, &supplier).await;
+            }
+            Err(err.into())
         }
-        Err(err_msg) => Err(AppError::unexpected(&err_msg)),
     }
 }
 
-async fn create_api_key(State(state): State<Arc<AppState>>) -> Result<impl IntoResponse, AppError> {
-    let supplier = state.create_supplier()
+async fn create_api_key(State(state): State<Arc<AppState>>, audit: Audit) -> Result<impl IntoResponse, AppError> {
+    let (supplier, issued) = state.create_supplier("default")
+        .await
+        .map_err(|err| AppError::unexpected(&err))?;
+    audit.for_supplier(supplier.api_key)
+        .record("create_api_key", Some(issued.key.id.to_string()), None, None)
+        .await;
+
+    Ok((StatusCode::CREATED, issued.api_key.to_string()))
+}
+
+#[derive(Deserialize)]
+struct SetPlan {
+    // the supplier id, as in /admin/suppliers/:api_key/plan
+    api_key: Uuid,
+    plan: String,
+}
+
+impl Validate for SetPlan {
+    fn validate(&self, violations: &mut Violations) {
+        violations.check(!self.plan.trim().is_empty(), "plan", "is required");
+    }
+}
+
+async fn set_plan(
+    State(state): State<Arc<AppState>>,
+    audit: Audit,
+    ValidJson(input): ValidJson<SetPlan>,
+) -> Result<impl IntoResponse, AppError> {
+    change_plan(&state, audit, &input.api_key, &input.plan).await
+}
+
+async fn change_plan(state: &AppState, audit: Audit, api_key: &Uuid, plan: &str) -> Result<Json<Ok>, AppError> {
+    let supplier = admin_supplier(state, api_key).await?;
+    if state.get_plan(plan).await.map_err(|err| AppError::unexpected(&err))?.is_none() {
+        return Err(AppError::invalid("plan", "is not a known plan"));
+    }
+
+    state.set_plan(api_key, plan)
         .await
         .map_err(|err| AppError::unexpected(&err))?;
+    audit.for_supplier(*api_key)
+        .record("set_plan", None, Some(json!(supplier.plan)), Some(json!(plan)))
+        .await;
+
+    Ok(Json(Ok { ok: true }))
+}
+
+async fn get_plan(state: &AppState, supplier: &Supplier) -> Result<Plan, AppError> {
+    state.get_plan(&supplier.plan)
+        .await
+        .map_err(|err| AppError::unexpected(&err))?
+        .ok_or_else(|| AppError::unexpected(&format!("Unknown plan {}", supplier.plan)))
+}
+
+async fn require_feature(state: &AppState, supplier: &Supplier, feature: &str) -> Result<(), AppError> {
+    check_feature(&get_plan(state, supplier).await?, feature)
+}
+
+fn check_feature(plan: &Plan, feature: &str) -> Result<(), AppError> {
+    if !plan.allows(feature) {
+        return Err(AppError::NoPermission(format!("Plan {} does not include {}", plan.id, feature)));
+    }
 
-    Ok((StatusCode::CREATED, supplier.api_key.to_string()))
+    Ok(())
 }
 
 #[derive(Deserialize)]
@@ -71,6 +332,18 @@ struct SetWbJwt {
     jwt: String,
 }
 
+impl Validate for SetWbJwt {
+    fn validate(&self, violations: &mut Violations) {
+        if self.jwt.trim().is_empty() {
+            violations.add("jwt", "is required");
+        } else if utils::get_jwt_expire(&self.jwt).is_err() {
+            violations.add("jwt", "must be a JWT with an exp claim");
+        } else if utils::is_jwt_expired(&self.jwt) {
+            violations.add("jwt", "is expired");
+        }
+    }
+}
+
 #[derive(Serialize)]
 struct Ok {
     ok: bool,
@@ -79,18 +352,90 @@ struct Ok {
 async fn set_wb_jwt(
     State(state): State<Arc<AppState>>,
     Extension(supplier): Extension<Supplier>,
-    Json(input): Json<SetWbJwt>,
+    audit: Audit,
+    ValidJson(input): ValidJson<SetWbJwt>,
 ) -> Result<impl IntoResponse, AppError> {
     state.set_wb_jwt(&supplier.api_key, &input.jwt)
         .await
         .map_err(|err| AppError::unexpected(&err))?;
+    // the token itself never goes to the audit log, only its expiry
+    let jwt_expiry = |jwt: &str| json!({ "expiry": utils::get_jwt_expire(jwt).ok().map(|expiry| expiry * 1000) });
+    audit.record(
+        "set_wb_jwt",
+        None,
+        supplier.wb_jwt.as_deref().map(jwt_expiry),
+        Some(jwt_expiry(&input.jwt)),
+    ).await;
+    if let Err(err) = state.request_sync(&supplier.api_key) {
+        error!("{}", err);
+    }
+
+    Ok(Json(Ok { ok: true }))
+}
+
+#[derive(Deserialize)]
+struct SetDryRun {
+    dry_run: bool,
+}
+
+async fn set_dry_run(
+    State(state): State<Arc<AppState>>,
+    Extension(supplier): Extension<Supplier>,
+    audit: Audit,
+    JsonInput(input): JsonInput<SetDryRun>,
+) -> Result<impl IntoResponse, AppError> {
+    if input.dry_run {
+        require_feature(&state, &supplier, Plan::DRY_RUN).await?;
+    }
+
+    state.set_dry_run(&supplier.api_key, input.dry_run)
+        .await
+        .map_err(|err| AppError::unexpected(&err))?;
+    audit.record("set_dry_run", None, Some(json!(supplier.dry_run)), Some(json!(input.dry_run))).await;
+
+    Ok(Json(Ok { ok: true }))
+}
+
+async fn sync(
+    State(state): State<Arc<AppState>>,
+    Extension(supplier): Extension<Supplier>,
+) -> Result<impl IntoResponse, AppError> {
+    state.request_sync(&supplier.api_key)
+        .map_err(|err| AppError::unexpected(&err))?;
+
+    Ok((StatusCode::ACCEPTED, Json(Ok { ok: true })))
+}
+
+#[derive(Deserialize)]
+struct SetTimezone {
+    timezone: String,
+}
+
+impl Validate for SetTimezone {
+    fn validate(&self, violations: &mut Violations) {
+        violations.check(self.timezone.parse::<Tz>().is_ok(), "timezone", "is not a known IANA timezone");
+    }
+}
+
+async fn set_timezone(
+    State(state): State<Arc<AppState>>,
+    Extension(supplier): Extension<Supplier>,
+    audit: Audit,
+    ValidJson(input): ValidJson<SetTimezone>,
+) -> Result<impl IntoResponse, AppError> {
+    state.set_timezone(&supplier.api_key, &input.timezone)
+        .await
+        .map_err(|err| AppError::unexpected(&err))?;
+    audit.record("set_timezone", None, Some(json!(supplier.timezone)), Some(json!(input.timezone))).await;
 
     Ok(Json(Ok { ok: true }))
 }
 
 #[derive(Serialize)]
 struct JwtState {
-    expiry: usize
+    expiry: usize,
+    expired: bool,
+    needs_reauth: bool,
 }
 
 #[derive(Serialize)]
@@ -102,7 +447,11 @@ struct Products {
 #[derive(Serialize)]
 struct UserState {
     jwt: Option<JwtState>,
-    products: Products
+    products: Products,
+    dry_run: bool,
+    active: bool,
+    timezone: String,
+    plan: Plan,
 }
 
 async fn get_state(
@@ -118,27 +467,1307 @@ async fn get_state(
     let current_monitored = state.count_by_apikey(&supplier.api_key)
         .await
         .map_err(|err| AppError::unexpected(&err))?;
-    let max_monitored = 100;
+    let plan = get_plan(&state, &supplier).await?;
 
     let us = UserState {
-        jwt: match jwt_expire_ts {
-            None => None,
-            Some(expiry) => Some(JwtState{ expiry: expiry * 1000 })
-        },
-        products: Products{ current: current_monitored as usize, max: max_monitored }
+        jwt: jwt_expire_ts.map(|expiry| JwtState{
+            expiry: expiry * 1000,
+            expired: supplier.wb_jwt.as_deref().is_some_and(utils::is_jwt_expired),
+            needs_reauth: supplier.needs_reauth,
+        }),
+        products: Products{ current: current_monitored as usize, max: plan.max_products as u32 },
+        dry_run: supplier.dry_run,
+        active: supplier.active,
+        timezone: supplier.timezone,
+        plan,
     };
 
     Ok(Json(us))
 }
 
+#[derive(Serialize)]
+#[serde(rename_all = "snake_case")]
+enum GoodState {
+    Paused,
+    Failed,
+    Pending,
+    Synced,
+    Drifted,
+}
+
+impl GoodState {
+    fn as_str(&self) -> &'static str {
+        match self {
+            GoodState::Paused => "paused",
+            GoodState::Failed => "failed",
+            GoodState::Pending => "pending",
+            GoodState::Synced => "synced",
+            GoodState::Drifted => "drifted",
+        }
+    }
+}
+
+#[derive(Serialize)]
+struct GoodView {
+    #[serde(flatten)]
+    good: GoodStatus,
+    target_price: i32,
+    status: GoodState,
+}
+
+impl GoodView {
+    /// `target_price` is the price the updater would upload now, the status is judged against it.
+    fn new(good: GoodStatus, target_price: i32) -> Self {
+        let status = if !good.active {
+            GoodState::Paused
+        } else if good.error.is_some() {
+            GoodState::Failed
+        } else {
+            match (good.observed_basic, good.observed_total) {
+                (Some(basic), Some(total)) if basic > 0 => {
+                    let (discounted, _) = calc::count_new_basic(target_price, total, basic);
+                    if total / 100 == discounted { GoodState::Synced } else { GoodState::Drifted }
+                }
+                _ => GoodState::Pending,
+            }
+        };
+
+        GoodView { good, target_price, status }
+    }
+}
+
+async fn good_views(state: &AppState, supplier: &Supplier, goods: Vec<GoodStatus>) -> Result<Vec<GoodView>, Error> {
+    let products = goods.iter().map(|good| Product::new(good.id, good.price)).collect();
+    let targets: HashMap<i32, i32> = update::target_prices(state, supplier, products)
+        .await?
+        .into_iter()
+        .map(|product| (product.id, product.price))
+        .collect();
+
+    Ok(goods
+        .into_iter()
+        .map(|good| {
+            let target_price = targets.get(&good.id).copied().unwrap_or(good.price);
+            GoodView::new(good, target_price)
+        })
+        .collect())
+}
+
+#[derive(Serialize)]
+struct GoodList {
+    goods: Vec<GoodView>,
+}
+
+async fn get_goods(
+    ValidQuery(pagination): ValidQuery<Pagination>,
+    QueryInput(filter): QueryInput<GoodsFilter>,
+    State(state): State<Arc<AppState>>,
+    Extension(supplier): Extension<Supplier>,
+) -> Result<impl IntoResponse, AppError> {
+    let (limit, page) = pagination.limit_and_page();
+
+    let goods = state.get_goods_status(&supplier.api_key, &filter, limit, page)
+        .await
+        .map_err(|err| AppError::unexpected(&err))?;
+    let goods = good_views(&state, &supplier, goods)
+        .await
+        .map_err(|err| AppError::unexpected(&err))?;
+
+    Ok(Json(GoodList { goods }))
+}
+
+const GOODS_EXPORT_HEADER: &[&str] = &[
+    "nm_id", "vendor_code", "status", "active", "price", "target_price", "min_price", "max_price",
+    "observed_basic", "observed_total", "observed_at", "uploaded_price", "uploaded_at", "error",
+];
+
+fn good_row(view: GoodView) -> Vec<Cell> {
+    let GoodView { good, target_price, status } = view;
+
+    vec![
+        Cell::Number(Some(good.id as f64)),
+        Cell::Text(good.vendor_code),
+        Cell::Text(Some(status.as_str().to_string())),
+        Cell::Text(Some(good.active.to_string())),
+        Cell::Number(Some(good.price as f64)),
+        Cell::Number(Some(target_price as f64)),
+        Cell::Number(good.min_price.map(f64::from)),
+        Cell::Number(good.max_price.map(f64::from)),
+        Cell::Number(good.observed_basic.map(f64::from)),
+        Cell::Number(good.observed_total.map(f64::from)),
+        Cell::Text(good.observed_at.map(|at| at.to_rfc3339())),
+        Cell::Number(good.uploaded_price.map(f64::from)),
+        Cell::Text(good.uploaded_at.map(|at| at.to_rfc3339())),
+        Cell::Text(good.error),
+    ]
+}
+
+async fn export_goods(
+    QueryInput(query): QueryInput<ExportQuery>,
+    QueryInput(filter): QueryInput<GoodsFilter>,
+    State(state): State<Arc<AppState>>,
+    Extension(supplier): Extension<Supplier>,
+) -> Result<Response, AppError> {
+    let pages = stream::unfold(Some(1), move |page| {
+        let (state, supplier, filter) = (state.clone(), supplier.clone(), filter.clone());
+        async move {
+            let page = page?;
+            let goods = match state.get_goods_status(&supplier.api_key, &filter, export::PAGE_SIZE, page).await {
+                Ok(goods) if goods.is_empty() => return None,
+                Ok(goods) => goods,
+                Err(err) => return Some((Err(err.to_string()), None)),
+            };
+            let next = (goods.len() == export::PAGE_SIZE).then_some(page + 1);
+            match good_views(&state, &supplier, goods).await {
+                Ok(views) => Some((Ok(views.into_iter().map(good_row).collect()), next)),
+                Err(err) => Some((Err(err.to_string()), None)),
+            }
+        }
+    });
+
+    export::respond(query.format, "goods", GOODS_EXPORT_HEADER, Box::pin(pages)).await
+}
+
+#[derive(Deserialize)]
+struct PutGood {
+    price: i32,
+    // required, a PUT that forgot it must not resume a paused good
+    active: bool,
+    vendor_code: Option<String>,
+    min_price: Option<i32>,
+    max_price: Option<i32>,
+}
+
+impl Validate for PutGood {
+    fn validate(&self, violations: &mut Violations) {
+        check_good(violations, Some(self.price), self.min_price, self.max_price, self.vendor_code.as_deref());
+    }
+}
+
+// tells an absent field (keep) from an explicit null (clear)
+fn nullable<'de, D, T>(deserializer: D) -> Result<Option<Option<T>>, D::Error>
+where
+    D: Deserializer<'de>,
+    T: Deserialize<'de>,
+{
+    Option::<T>::deserialize(deserializer).map(Some)
+}
+
+#[derive(Deserialize)]
+struct PatchGood {
+    price: Option<i32>,
+    active: Option<bool>,
+    #[serde(default, deserialize_with = "nullable")]
+    vendor_code: Option<Option<String>>,
+    #[serde(default, deserialize_with = "nullable")]
+    min_price: Option<Option<i32>>,
+    #[serde(default, deserialize_with = "nullable")]
+    max_price: Option<Option<i32>>,
+}
+
+impl Validate for PatchGood {
+    fn validate(&self, violations: &mut Violations) {
+        let vendor_code = self.vendor_code.as_ref().and_then(Option::as_deref);
+        check_good(violations, self.price, self.min_price.flatten(), self.max_price.flatten(), vendor_code);
+    }
+}
+
+impl Validate for GoodSettings {
+    fn validate(&self, violations: &mut Violations) {
+        check_good(violations, Some(self.price), self.min_price, self.max_price, self.vendor_code.as_deref());
+    }
+}
+
+fn check_good(
+    violations: &mut Violations,
+    price: Option<i32>,
+    min_price: Option<i32>,
+    max_price: Option<i32>,
+    vendor_code: Option<&str>,
+) {
+    for (field, value) in [("price", price), ("min_price", min_price), ("max_price", max_price)] {
+        if let Some(value) = value {
+            violations.check(value > 0, field, "must be positive");
+        }
+    }
+    if let (Some(min), Some(max)) = (min_price, max_price) {
+        violations.check(min <= max, "min_price", "must not exceed max_price");
+    }
+    if let (Some(price), Some(min)) = (price, min_price) {
+        violations.check(price >= min, "price", "must not be below min_price");
+    }
+    if let (Some(price), Some(max)) = (price, max_price) {
+        violations.check(price <= max, "price", "must not be above max_price");
+    }
+    if let Some(code) = vendor_code {
+        violations.check(!code.trim().is_empty(), "vendor_code", "must not be empty");
+    }
+}
+
+async fn put_good(
+    ValidPath(GoodPath { good_id }): ValidPath<GoodPath>,
+    State(state): State<Arc<AppState>>,
+    Extension(supplier): Extension<Supplier>,
+    audit: Audit,
+    ValidJson(input): ValidJson<PutGood>,
+) -> Result<impl IntoResponse, AppError> {
+    let good = GoodSettings {
+        id: good_id,
+        price: input.price,
+        active: input.active,
+        vendor_code: input.vendor_code,
+        min_price: input.min_price,
+        max_price: input.max_price,
+    };
+
+    let plan = get_plan(&state, &supplier).await?;
+    let old = state.save_good_settings(&supplier.api_key, &good, &plan).await?;
+    let inserted = old.is_none();
+    audit.record("put_good", Some(good_id.to_string()), old.map(|old| json!(old)), Some(json!(good))).await;
+    if let Err(err) = state.request_sync(&supplier.api_key) {
+        error!("{}", err);
+    }
+
+    let status = if inserted { StatusCode::CREATED } else { StatusCode::OK };
+    Ok((status, Json(good)))
+}
+
+async fn patch_good(
+    ValidPath(GoodPath { good_id }): ValidPath<GoodPath>,
+    State(state): State<Arc<AppState>>,
+    Extension(supplier): Extension<Supplier>,
+    audit: Audit,
+    ValidJson(input): ValidJson<PatchGood>,
+) -> Result<impl IntoResponse, AppError> {
+    // the write only lands if nobody changed the good since it was read, otherwise read it again
+    for _ in 0..PATCH_ATTEMPTS {
+        let old = state.get_good_settings(&supplier.api_key, good_id)
+            .await
+            .map_err(|err| AppError::unexpected(&err))?
+            .ok_or_else(|| AppError::NotFound(format!("Good {} not found", good_id)))?;
+
+        let good = GoodSettings {
+            id: good_id,
+            price: input.price.unwrap_or(old.price),
+            active: input.active.unwrap_or(old.active),
+            vendor_code: input.vendor_code.clone().unwrap_or_else(|| old.vendor_code.clone()),
+            min_price: input.min_price.unwrap_or(old.min_price),
+            max_price: input.max_price.unwrap_or(old.max_price),
+        };
+        // the patch may only make sense together with the stored bounds
+        good.check()?;
+
+        if !state.update_good_settings(&supplier.api_key, &old, &good).await.map_err(|err| AppError::unexpected(&err))? {
+            continue;
+        }
+        audit.record("patch_good", Some(good_id.to_string()), Some(json!(old)), Some(json!(good))).await;
+        if let Err(err) = state.request_sync(&supplier.api_key) {
+            error!("{}", err);
+        }
+
+        return Ok(Json(good));
+    }
+
+    Err(AppError::Conflict(format!("Good {} keeps changing, retry", good_id)))
+}
+
 async fn delete_good(
-    Path(good_id): Path<i32>,
+    ValidPath(GoodPath { good_id }): ValidPath<GoodPath>,
     State(state): State<Arc<AppState>>,
     Extension(supplier): Extension<Supplier>,
+    audit: Audit,
 ) -> Result<impl IntoResponse, AppError> {
-    state.delete_by_id_and_api_key(good_id, &supplier.api_key)
+    let old = state.get_good(&supplier.api_key, good_id)
         .await
         .map_err(|err| AppError::unexpected(&err))?;
+    if !state.delete_by_id_and_api_key(good_id, &supplier.api_key).await.map_err(|err| AppError::unexpected(&err))? {
+        return Err(AppError::NotFound(format!("Good {} not found", good_id)));
+    }
+    audit.record("delete_good", Some(good_id.to_string()), old.map(|good| json!(good)), None).await;
+    // no sync: there is nothing to upload, WB keeps its current price and the updater just stops managing the good
 
     Ok(StatusCode::NO_CONTENT)
 }
+
+#[derive(Deserialize)]
+struct BulkGoods {
+    products: Vec<Product>,
+}
+
+impl Validate for BulkGoods {
+    fn validate(&self, violations: &mut Violations) {
+        violations.check(!self.products.is_empty(), "products", "must not be empty");
+    }
+}
+
+#[derive(Serialize)]
+struct RejectedGood {
+    id: i32,
+    reason: String,
+}
+
+#[derive(Serialize)]
+struct BulkReport {
+    inserted: Vec<i32>,
+    updated: Vec<i32>,
+    rejected: Vec<RejectedGood>,
+}
+
+async fn bulk_upsert_goods(
+    State(state): State<Arc<AppState>>,
+    Extension(supplier): Extension<Supplier>,
+    audit: Audit,
+    ValidJson(input): ValidJson<BulkGoods>,
+) -> Result<impl IntoResponse, AppError> {
+    let plan = get_plan(&state, &supplier).await?;
+    check_feature(&plan, Plan::BULK_GOODS)?;
+
+    let mut seen = HashSet::new();
+    let mut accepted = Vec::with_capacity(input.products.len());
+    let mut rejected = Vec::new();
+    for product in input.products {
+        let reason = if product.id <= 0 {
+            Some("Id must be positive")
+        } else if product.price <= 0 {
+            Some("Price must be positive")
+        } else if !seen.insert(product.id) {
+            Some("Duplicate id in request")
+        } else {
+            None
+        };
+
+        match reason {
+            Some(reason) => rejected.push(RejectedGood { id: product.id, reason: reason.to_string() }),
+            None => accepted.push(product),
+        }
+    }
+
+    let upserted = state.add_goods(&supplier.api_key, &accepted, &plan).await?;
+    let (inserted, updated): (Vec<_>, Vec<_>) = upserted.into_iter().partition(|good| good.inserted);
+    let report = BulkReport {
+        inserted: inserted.into_iter().map(|good| good.id).collect(),
+        updated: updated.into_iter().map(|good| good.id).collect(),
+        rejected,
+    };
+
+    if !accepted.is_empty() {
+        audit.record("bulk_upsert_goods", None, None, Some(json!(accepted))).await;
+        if let Err(err) = state.request_sync(&supplier.api_key) {
+            error!("{}", err);
+        }
+    }
+
+    Ok(Json(report))
+}
+
+#[derive(Serialize)]
+struct ImportReport {
+    inserted: Vec<i32>,
+    updated: Vec<i32>,
+    errors: Vec<RowError>,
+}
+
+async fn import_goods(
+    State(state): State<Arc<AppState>>,
+    Extension(supplier): Extension<Supplier>,
+    audit: Audit,
+    mut multipart: Multipart,
+) -> Result<impl IntoResponse, AppError> {
+    let plan = get_plan(&state, &supplier).await?;
+    check_feature(&plan, Plan::BULK_GOODS)?;
+
+    let mut file = None;
+    while let Some(field) = multipart.next_field().await.map_err(|err| AppError::InvalidInput(err.to_string()))? {
+        if field.name() != Some("file") {
+            continue;
+        }
+        let format = import::Format::detect(field.file_name(), field.content_type())
+            .ok_or_else(|| AppError::InvalidInput("File must be .csv or .xlsx".to_string()))?;
+        let data = field.bytes().await.map_err(|err| AppError::InvalidInput(err.to_string()))?;
+        file = Some((format, data));
+    }
+    let (format, data) = file.ok_or_else(|| AppError::InvalidInput("Multipart field file is required".to_string()))?;
+    let (rows, mut errors) = import::parse(format, &data).map_err(AppError::InvalidInput)?;
+
+    // rows without nm_id can only update goods that already have this vendor code
+    let vendor_codes: Vec<String> = rows
+        .iter()
+        .filter(|row| row.nm_id.is_none())
+        .filter_map(|row| row.vendor_code.clone())
+        .collect();
+    let known: HashMap<String, i32> = state.find_goods_by_vendor_codes(&supplier.api_key, &vendor_codes)
+        .await
+        .map_err(|err| AppError::unexpected(&err))?
+        .into_iter()
+        .collect();
+
+    let products = import::resolve(rows, &known, &mut errors);
+    errors.sort_by_key(|error| error.row);
+
+    let upserted = state.import_goods(&supplier.api_key, &products, &plan).await?;
+    let (inserted, updated): (Vec<_>, Vec<_>) = upserted.into_iter().partition(|good| good.inserted);
+
+    if !products.is_empty() {
+        audit.record("import_goods", None, None, Some(json!(products))).await;
+        if let Err(err) = state.request_sync(&supplier.api_key) {
+            error!("{}", err);
+        }
+    }
+
+    Ok(Json(ImportReport {
+        inserted: inserted.into_iter().map(|good| good.id).collect(),
+        updated: updated.into_iter().map(|good| good.id).collect(),
+        errors,
+    }))
+}
+
+#[derive(Deserialize)]
+struct Pagination {
+    limit: Option<usize>,
+    page: Option<usize>,
+}
+
+const MAX_LIMIT: usize = 500;
+const MAX_PAGE: usize = 1_000_000;
+
+impl Validate for Pagination {
+    fn validate(&self, violations: &mut Violations) {
+        if let Some(limit) = self.limit {
+            violations.check((1..=MAX_LIMIT).contains(&limit), "limit", &format!("must be between 1 and {}", MAX_LIMIT));
+        }
+        if let Some(page) = self.page {
+            violations.check((1..=MAX_PAGE).contains(&page), "page", &format!("must be between 1 and {}", MAX_PAGE));
+        }
+    }
+}
+
+impl Pagination {
+    fn limit_and_page(&self) -> (usize, usize) {
+        (self.limit.unwrap_or(50), self.page.unwrap_or(1))
+    }
+}
+
+#[derive(Serialize)]
+struct RunList {
+    runs: Vec<Run>,
+}
+
+async fn get_runs(
+    ValidQuery(pagination): ValidQuery<Pagination>,
+    State(state): State<Arc<AppState>>,
+    Extension(supplier): Extension<Supplier>,
+) -> Result<impl IntoResponse, AppError> {
+    let (limit, page) = pagination.limit_and_page();
+
+    let runs = state.get_runs(&supplier.api_key, limit, page)
+        .await
+        .map_err(|err| AppError::unexpected(&err))?;
+
+    Ok(Json(RunList { runs }))
+}
+
+#[derive(Serialize)]
+struct PlannedChangeList {
+    changes: Vec<PlannedChange>,
+}
+
+async fn get_planned_changes(
+    ValidQuery(pagination): ValidQuery<Pagination>,
+    State(state): State<Arc<AppState>>,
+    Extension(supplier): Extension<Supplier>,
+) -> Result<impl IntoResponse, AppError> {
+    let (limit, page) = pagination.limit_and_page();
+
+    let changes = state.get_planned_changes(&supplier.api_key, false, limit, page)
+        .await
+        .map_err(|err| AppError::unexpected(&err))?;
+
+    Ok(Json(PlannedChangeList { changes }))
+}
+
+async fn get_frozen_changes(
+    ValidQuery(pagination): ValidQuery<Pagination>,
+    State(state): State<Arc<AppState>>,
+    Extension(supplier): Extension<Supplier>,
+) -> Result<impl IntoResponse, AppError> {
+    let (limit, page) = pagination.limit_and_page();
+
+    let changes = state.get_planned_changes(&supplier.api_key, true, limit, page)
+        .await
+        .map_err(|err| AppError::unexpected(&err))?;
+
+    Ok(Json(PlannedChangeList { changes }))
+}
+
+#[derive(Serialize)]
+struct AuditLog {
+    entries: Vec<AuditEntry>,
+}
+
+async fn get_audit_log(
+    ValidQuery(pagination): ValidQuery<Pagination>,
+    State(state): State<Arc<AppState>>,
+    Extension(supplier): Extension<Supplier>,
+) -> Result<impl IntoResponse, AppError> {
+    let (limit, page) = pagination.limit_and_page();
+
+    let entries = state.get_audit_log(&supplier.api_key, limit, page)
+        .await
+        .map_err(|err| AppError::unexpected(&err))?;
+
+    Ok(Json(AuditLog { entries }))
+}
+
+async fn set_good_active(state: &AppState, supplier: &Supplier, audit: &Audit, good_id: i32, active: bool) -> Result<(), AppError> {
+    let updated = state.set_goods_active(&supplier.api_key, Some(&[good_id]), active)
+        .await
+        .map_err(|err| AppError::unexpected(&err))?;
+
+    if updated == 0 {
+        return Err(AppError::NotFound(format!("Good {} not found", good_id)));
+    }
+    audit.record("set_good_active", Some(good_id.to_string()), None, Some(json!(active))).await;
+
+    Ok(())
+}
+
+async fn pause_good(
+    ValidPath(GoodPath { good_id }): ValidPath<GoodPath>,
+    State(state): State<Arc<AppState>>,
+    Extension(supplier): Extension<Supplier>,
+    audit: Audit,
+) -> Result<impl IntoResponse, AppError> {
+    set_good_active(&state, &supplier, &audit, good_id, false).await?;
+
+    Ok(Json(Ok { ok: true }))
+}
+
+async fn resume_good(
+    ValidPath(GoodPath { good_id }): ValidPath<GoodPath>,
+    State(state): State<Arc<AppState>>,
+    Extension(supplier): Extension<Supplier>,
+    audit: Audit,
+) -> Result<impl IntoResponse, AppError> {
+    set_good_active(&state, &supplier, &audit, good_id, true).await?;
+
+    Ok(Json(Ok { ok: true }))
+}
+
+#[derive(Deserialize)]
+struct GoodIds {
+    ids: Option<Vec<i32>>,
+    #[serde(default)]
+    all: bool,
+}
+
+impl Validate for GoodIds {
+    fn validate(&self, violations: &mut Violations) {
+        let Some(ids) = &self.ids else {
+            violations.check(self.all, "ids", "is required, pass \"all\": true to update all goods");
+            return;
+        };
+        violations.check(!self.all, "all", "must not be combined with ids");
+        violations.check(!ids.is_empty(), "ids", "must not be empty, pass \"all\": true to update all goods");
+        for (index, id) in ids.iter().enumerate() {
+            violations.check(*id > 0, &format!("ids[{}]", index), "must be positive");
+        }
+    }
+}
+
+#[derive(Serialize)]
+struct Updated {
+    updated: u64,
+}
+
+async fn pause_goods(
+    State(state): State<Arc<AppState>>,
+    Extension(supplier): Extension<Supplier>,
+    audit: Audit,
+    ValidJson(input): ValidJson<GoodIds>,
+) -> Result<impl IntoResponse, AppError> {
+    let updated = state.set_goods_active(&supplier.api_key, input.ids.as_deref(), false)
+        .await
+        .map_err(|err| AppError::unexpected(&err))?;
+    audit.record("set_goods_active", input.ids.map(|ids| json!(ids).to_string()), None, Some(json!(false))).await;
+
+    Ok(Json(Updated { updated }))
+}
+
+async fn resume_goods(
+    State(state): State<Arc<AppState>>,
+    Extension(supplier): Extension<Supplier>,
+    audit: Audit,
+    ValidJson(input): ValidJson<GoodIds>,
+) -> Result<impl IntoResponse, AppError> {
+    let updated = state.set_goods_active(&supplier.api_key, input.ids.as_deref(), true)
+        .await
+        .map_err(|err| AppError::unexpected(&err))?;
+    audit.record("set_goods_active", input.ids.map(|ids| json!(ids).to_string()), None, Some(json!(true))).await;
+
+    Ok(Json(Updated { updated }))
+}
+
+async fn pause_supplier(
+    State(state): State<Arc<AppState>>,
+    Extension(supplier): Extension<Supplier>,
+    audit: Audit,
+) -> Result<impl IntoResponse, AppError> {
+    state.set_supplier_active(&supplier.api_key, false)
+        .await
+        .map_err(|err| AppError::unexpected(&err))?;
+    audit.record("set_active", None, Some(json!(supplier.active)), Some(json!(false))).await;
+
+    Ok(Json(Ok { ok: true }))
+}
+
+async fn resume_supplier(
+    State(state): State<Arc<AppState>>,
+    Extension(supplier): Extension<Supplier>,
+    audit: Audit,
+) -> Result<impl IntoResponse, AppError> {
+    state.set_supplier_active(&supplier.api_key, true)
+        .await
+        .map_err(|err| AppError::unexpected(&err))?;
+    audit.record("set_active", None, Some(json!(supplier.active)), Some(json!(true))).await;
+    if let Err(err) = state.request_sync(&supplier.api_key) {
+        error!("{}", err);
+    }
+
+    Ok(Json(Ok { ok: true }))
+}
+
+#[derive(Deserialize, Serialize)]
+struct Schedule {
+    windows: Vec<PriceWindow>,
+}
+
+impl Validate for Schedule {
+    fn validate(&self, violations: &mut Violations) {
+        for (index, window) in self.windows.iter().enumerate() {
+            let field = |name: &str| format!("windows[{}].{}", index, name);
+            violations.check(window.price > 0, &field("price"), "must be positive");
+            violations.check(
+                !window.weekdays.iter().flatten().any(|day| !(1..=7).contains(day)),
+                &field("weekdays"),
+                "must be in 1..=7, Monday is 1",
+            );
+            if let (Some(start), Some(end)) = (window.start_date, window.end_date) {
+                violations.check(start <= end, &field("start_date"), "must not be after end_date");
+            }
+        }
+    }
+}
+
+async fn get_schedule(
+    ValidPath(GoodPath { good_id }): ValidPath<GoodPath>,
+    State(state): State<Arc<AppState>>,
+    Extension(supplier): Extension<Supplier>,
+) -> Result<impl IntoResponse, AppError> {
+    let windows = state.get_schedule(&supplier.api_key, good_id)
+        .await
+        .map_err(|err| AppError::unexpected(&err))?;
+
+    Ok(Json(Schedule { windows }))
+}
+
+async fn set_schedule(
+    ValidPath(GoodPath { good_id }): ValidPath<GoodPath>,
+    State(state): State<Arc<AppState>>,
+    Extension(supplier): Extension<Supplier>,
+    audit: Audit,
+    ValidJson(input): ValidJson<Schedule>,
+) -> Result<impl IntoResponse, AppError> {
+    if !input.windows.is_empty() {
+        require_feature(&state, &supplier, Plan::SCHEDULES).await?;
+    }
+
+    let old = state.get_schedule(&supplier.api_key, good_id)
+        .await
+        .map_err(|err| AppError::unexpected(&err))?;
+    if !state.set_schedule(&supplier.api_key, good_id, &input.windows).await.map_err(|err| AppError::unexpected(&err))? {
+        return Err(AppError::NotFound(format!("Good {} not found", good_id)));
+    }
+    audit.record("set_schedule", Some(good_id.to_string()), Some(json!(old)), Some(json!(input.windows))).await;
+    if let Err(err) = state.request_sync(&supplier.api_key) {
+        error!("{}", err);
+    }
+
+    Ok(Json(Ok { ok: true }))
+}
+
+#[derive(Deserialize)]
+struct NewCampaign {
+    name: String,
+    starts_at: DateTime<Utc>,
+    ends_at: DateTime<Utc>,
+    products: Vec<Product>,
+}
+
+impl Validate for NewCampaign {
+    fn validate(&self, violations: &mut Violations) {
+        violations.check(!self.name.trim().is_empty(), "name", "is required");
+        violations.check(self.starts_at < self.ends_at, "starts_at", "must be before ends_at");
+        violations.check(!self.products.is_empty(), "products", "must not be empty");
+
+        let mut seen = HashSet::new();
+        for (index, product) in self.products.iter().enumerate() {
+            let field = |name: &str| format!("products[{}].{}", index, name);
+            violations.check(product.id > 0, &field("id"), "must be positive");
+            violations.check(seen.insert(product.id), &field("id"), "is duplicated");
+            violations.check(product.price > 0, &field("price"), "must be positive");
+        }
+    }
+}
+
+#[derive(Serialize)]
+struct CampaignList {
+    campaigns: Vec<Campaign>,
+}
+
+#[derive(Serialize)]
+struct CampaignDetails {
+    #[serde(flatten)]
+    campaign: Campaign,
+    products: Vec<Product>,
+}
+
+async fn create_campaign(
+    State(state): State<Arc<AppState>>,
+    Extension(supplier): Extension<Supplier>,
+    audit: Audit,
+    ValidJson(input): ValidJson<NewCampaign>,
+) -> Result<impl IntoResponse, AppError> {
+    require_feature(&state, &supplier, Plan::CAMPAIGNS).await?;
+
+    let campaign = state.create_campaign(&supplier.api_key, &input.name, input.starts_at, input.ends_at, &input.products)
+        .await
+        .map_err(|err| AppError::unexpected(&err))?
+        .ok_or_else(|| AppError::invalid("products", "must all be monitored goods"))?;
+    audit.record(
+        "create_campaign",
+        Some(campaign.id.to_string()),
+        None,
+        Some(json!(CampaignDetails { campaign: campaign.clone(), products: input.products.clone() })),
+    ).await;
+    if campaign.starts_at <= Utc::now() {
+        if let Err(err) = state.request_sync(&supplier.api_key) {
+            error!("{}", err);
+        }
+    }
+
+    Ok((StatusCode::CREATED, Json(CampaignDetails { campaign, products: input.products })))
+}
+
+async fn get_campaigns(
+    State(state): State<Arc<AppState>>,
+    Extension(supplier): Extension<Supplier>,
+) -> Result<impl IntoResponse, AppError> {
+    let campaigns = state.get_campaigns(&supplier.api_key)
+        .await
+        .map_err(|err| AppError::unexpected(&err))?;
+
+    Ok(Json(CampaignList { campaigns }))
+}
+
+async fn get_campaign(
+    ValidPath(CampaignPath { campaign_id }): ValidPath<CampaignPath>,
+    State(state): State<Arc<AppState>>,
+    Extension(supplier): Extension<Supplier>,
+) -> Result<impl IntoResponse, AppError> {
+    let (campaign, products) = state.get_campaign(&supplier.api_key, campaign_id)
+        .await
+        .map_err(|err| AppError::unexpected(&err))?
+        .ok_or_else(|| AppError::NotFound(format!("Campaign {} not found", campaign_id)))?;
+
+    Ok(Json(CampaignDetails { campaign, products }))
+}
+
+async fn delete_campaign(
+    ValidPath(CampaignPath { campaign_id }): ValidPath<CampaignPath>,
+    State(state): State<Arc<AppState>>,
+    Extension(supplier): Extension<Supplier>,
+    audit: Audit,
+) -> Result<impl IntoResponse, AppError> {
+    let (campaign, products) = state.get_campaign(&supplier.api_key, campaign_id)
+        .await
+        .map_err(|err| AppError::unexpected(&err))?
+        .ok_or_else(|| AppError::NotFound(format!("Campaign {} not found", campaign_id)))?;
+    state.delete_campaign(&supplier.api_key, campaign_id)
+        .await
+        .map_err(|err| AppError::unexpected(&err))?;
+    audit.record(
+        "delete_campaign",
+        Some(campaign_id.to_string()),
+        Some(json!(CampaignDetails { campaign, products })),
+        None,
+    ).await;
+    if let Err(err) = state.request_sync(&supplier.api_key) {
+        error!("{}", err);
+    }
+
+    Ok(StatusCode::NO_CONTENT)
+}
+
+#[derive(Deserialize)]
+struct NewFreeze {
+    starts_at: DateTime<Utc>,
+    ends_at: DateTime<Utc>,
+    reason: Option<String>,
+}
+
+impl Validate for NewFreeze {
+    fn validate(&self, violations: &mut Violations) {
+        violations.check(self.starts_at < self.ends_at, "starts_at", "must be before ends_at");
+        if let Some(reason) = &self.reason {
+            violations.check(!reason.trim().is_empty(), "reason", "must not be empty");
+        }
+    }
+}
+
+#[derive(Serialize)]
+struct FreezeList {
+    freezes: Vec<FreezeWindow>,
+}
+
+async fn create_freeze(
+    State(state): State<Arc<AppState>>,
+    Extension(supplier): Extension<Supplier>,
+    audit: Audit,
+    ValidJson(input): ValidJson<NewFreeze>,
+) -> Result<impl IntoResponse, AppError> {
+    require_feature(&state, &supplier, Plan::FREEZES).await?;
+
+    let freeze = state.create_freeze(&supplier.api_key, input.starts_at, input.ends_at, input.reason.as_deref())
+        .await
+        .map_err(|err| AppError::unexpected(&err))?;
+    audit.record("create_freeze", Some(freeze.id.to_string()), None, Some(json!(freeze))).await;
+
+    Ok((StatusCode::CREATED, Json(freeze)))
+}
+
+async fn get_freezes(
+    State(state): State<Arc<AppState>>,
+    Extension(supplier): Extension<Supplier>,
+) -> Result<impl IntoResponse, AppError> {
+    let freezes = state.get_freezes(&supplier.api_key)
+        .await
+        .map_err(|err| AppError::unexpected(&err))?;
+
+    Ok(Json(FreezeList { freezes }))
+}
+
+async fn delete_freeze(
+    ValidPath(FreezePath { freeze_id }): ValidPath<FreezePath>,
+    State(state): State<Arc<AppState>>,
+    Extension(supplier): Extension<Supplier>,
+    audit: Audit,
+) -> Result<impl IntoResponse, AppError> {
+    if !state.delete_freeze(&supplier.api_key, freeze_id).await.map_err(|err| AppError::unexpected(&err))? {
+        return Err(AppError::NotFound(format!("Freeze {} not found", freeze_id)));
+    }
+    audit.record("delete_freeze", Some(freeze_id.to_string()), None, None).await;
+
+    Ok(StatusCode::NO_CONTENT)
+}
+
+#[derive(Deserialize)]
+struct NewApiKey {
+    label: String,
+    expires_at: Option<DateTime<Utc>>,
+}
+
+impl Validate for NewApiKey {
+    fn validate(&self, violations: &mut Violations) {
+        violations.check(!self.label.trim().is_empty(), "label", "is required");
+        if let Some(expires_at) = self.expires_at {
+            violations.check(expires_at > Utc::now(), "expires_at", "must be in the future");
+        }
+    }
+}
+
+#[derive(Serialize)]
+struct ApiKeyList {
+    api_keys: Vec<ApiKey>,
+}
+
+async fn create_supplier_api_key(
+    State(state): State<Arc<AppState>>,
+    Extension(supplier): Extension<Supplier>,
+    audit: Audit,
+    ValidJson(input): ValidJson<NewApiKey>,
+) -> Result<impl IntoResponse, AppError> {
+    let issued = state.create_api_key(&supplier.api_key, input.label.trim(), input.expires_at)
+        .await
+        .map_err(|err| AppError::unexpected(&err))?;
+    // only the key's metadata goes to the audit log, never its value
+    audit.record("create_api_key", Some(issued.key.id.to_string()), None, Some(json!(issued.key))).await;
+
+    Ok((StatusCode::CREATED, Json(issued)))
+}
+
+async fn get_api_keys(
+    State(state): State<Arc<AppState>>,
+    Extension(supplier): Extension<Supplier>,
+) -> Result<impl IntoResponse, AppError> {
+    let api_keys = state.get_api_keys(&supplier.api_key)
+        .await
+        .map_err(|err| AppError::unexpected(&err))?;
+
+    Ok(Json(ApiKeyList { api_keys }))
+}
+
+async fn revoke_supplier_api_key(
+    ValidPath(KeyPath { key_id }): ValidPath<KeyPath>,
+    State(state): State<Arc<AppState>>,
+    Extension(supplier): Extension<Supplier>,
+    audit: Audit,
+) -> Result<impl IntoResponse, AppError> {
+    if !state.revoke_api_key(&supplier.api_key, key_id).await.map_err(|err| AppError::unexpected(&err))? {
+        return Err(AppError::NotFound(format!("Api key {} not found", key_id)));
+    }
+    audit.record("revoke_api_key", Some(key_id.to_string()), None, None).await;
+
+    Ok(StatusCode::NO_CONTENT)
+}
+
+#[derive(Deserialize)]
+struct HistoryQuery {
+    from: Option<DateTime<Utc>>,
+    to: Option<DateTime<Utc>>,
+    bucket: Option<i64>,
+}
+
+impl Validate for HistoryQuery {
+    fn validate(&self, violations: &mut Violations) {
+        check_range(violations, self.from, self.to);
+        if let Some(bucket) = self.bucket {
+            violations.check(bucket > 0, "bucket", "must be a positive number of seconds");
+        }
+    }
+}
+
+fn check_range(violations: &mut Violations, from: Option<DateTime<Utc>>, to: Option<DateTime<Utc>>) {
+    if let (Some(from), Some(to)) = (from, to) {
+        violations.check(from < to, "from", "must be before to");
+    }
+}
+
+#[derive(Serialize)]
+struct History {
+    observed: Vec<ObservedPrice>,
+    uploaded: Vec<UploadedPrice>,
+}
+
+async fn get_history(
+    ValidPath(GoodPath { good_id }): ValidPath<GoodPath>,
+    ValidQuery(query): ValidQuery<HistoryQuery>,
+    State(state): State<Arc<AppState>>,
+    Extension(supplier): Extension<Supplier>,
+) -> Result<impl IntoResponse, AppError> {
+    let to = query.to.unwrap_or_else(Utc::now);
+    let from = query.from.unwrap_or(to - chrono::Duration::days(7));
+    if from >= to {
+        return Err(AppError::invalid("from", "must be before to"));
+    }
+
+    let (observed, uploaded) = state.get_price_history(&supplier.api_key, good_id, from, to, query.bucket)
+        .await
+        .map_err(|err| AppError::unexpected(&err))?;
+
+    Ok(Json(History { observed, uploaded }))
+}
+
+#[derive(Deserialize)]
+struct HistoryExportQuery {
+    from: Option<DateTime<Utc>>,
+    to: Option<DateTime<Utc>>,
+    product_id: Option<i32>,
+}
+
+impl Validate for HistoryExportQuery {
+    fn validate(&self, violations: &mut Violations) {
+        check_range(violations, self.from, self.to);
+        if let Some(id) = self.product_id {
+            violations.check(id > 0, "product_id", "must be positive");
+        }
+    }
+}
+
+const HISTORY_EXPORT_HEADER: &[&str] = &["nm_id", "kind", "recorded_at", "basic", "total", "spp", "uploaded_price"];
+
+async fn export_history(
+    QueryInput(query): QueryInput<ExportQuery>,
+    ValidQuery(range): ValidQuery<HistoryExportQuery>,
+    State(state): State<Arc<AppState>>,
+    Extension(supplier): Extension<Supplier>,
+) -> Result<Response, AppError> {
+    let to = range.to.unwrap_or_else(Utc::now);
+    let from = range.from.unwrap_or(to - chrono::Duration::days(30));
+    if from >= to {
+        return Err(AppError::invalid("from", "must be before to"));
+    }
+
+    let api_key = supplier.api_key;
+    let pages = stream::unfold(Some(0), move |after_id| {
+        let state = state.clone();
+        async move {
+            let after_id = after_id?;
+            match state.get_history_after(&api_key, range.product_id, from, to, after_id, export::PAGE_SIZE).await {
+                Ok(records) if records.is_empty() => None,
+                Ok(records) => {
+                    let next = (records.len() == export::PAGE_SIZE).then(|| records[records.len() - 1].id);
+                    let rows = records
+                        .into_iter()
+                        .map(|record| vec![
+                            Cell::Number(Some(record.product_id as f64)),
+                            Cell::Text(Some(record.kind)),
+                            Cell::Text(Some(record.recorded_at.to_rfc3339())),
+                            Cell::Number(record.basic.map(f64::from)),
+                            Cell::Number(record.total.map(f64::from)),
+                            Cell::Number(record.spp),
+                            Cell::Number(record.price.map(f64::from)),
+                        ])
+                        .collect();
+                    Some((Ok(rows), next))
+                }
+                Err(err) => Some((Err(err.to_string()), None)),
+            }
+        }
+    });
+
+    export::respond(query.format, "history", HISTORY_EXPORT_HEADER, Box::pin(pages)).await
+}
+
+#[derive(Deserialize)]
+struct SupplierPath {
+    api_key: Uuid,
+}
+
+impl Validate for SupplierFilter {
+    fn validate(&self, violations: &mut Violations) {
+        if let Some(search) = &self.search {
+            violations.check(!search.trim().is_empty(), "search", "must not be empty");
+        }
+    }
+}
+
+#[derive(Serialize)]
+struct AdminSupplier {
+    api_key: Uuid,
+    wb_id: Option<i32>,
+    plan: String,
+    timezone: String,
+    active: bool,
+    dry_run: bool,
+    disabled: bool,
+    has_jwt: bool,
+    needs_reauth: bool,
+}
+
+impl From<Supplier> for AdminSupplier {
+    fn from(supplier: Supplier) -> Self {
+        AdminSupplier {
+            api_key: supplier.api_key,
+            wb_id: supplier.wb_id,
+            plan: supplier.plan,
+            timezone: supplier.timezone,
+            active: supplier.active,
+            dry_run: supplier.dry_run,
+            disabled: supplier.disabled,
+            has_jwt: supplier.wb_jwt.is_some(),
+            needs_reauth: supplier.needs_reauth,
+        }
+    }
+}
+
+#[derive(Serialize)]
+struct AdminSupplierList {
+    suppliers: Vec<AdminSupplier>,
+}
+
+#[derive(Serialize)]
+struct AdminSupplierDetails {
+    #[serde(flatten)]
+    supplier: AdminSupplier,
+    goods: i64,
+}
+
+#[derive(Serialize)]
+struct Revoked {
+    revoked: u64,
+}
+
+#[derive(Deserialize)]
+struct PlanChange {
+    plan: String,
+}
+
+impl Validate for PlanChange {
+    fn validate(&self, violations: &mut Violations) {
+        violations.check(!self.plan.trim().is_empty(), "plan", "is required");
+    }
+}
+
+async fn admin_supplier(state: &AppState, api_key: &Uuid) -> Result<Supplier, AppError> {
+    state.get_supplier(api_key)
+        .await
+        .map_err(|err| match err {
+            Error::InvalidApiKey => AppError::NotFound(format!("Supplier {} not found", api_key)),
+            err => AppError::unexpected(&err),
+        })
+}
+
+async fn admin_get_suppliers(
+    ValidQuery(pagination): ValidQuery<Pagination>,
+    ValidQuery(filter): ValidQuery<SupplierFilter>,
+    State(state): State<Arc<AppState>>,
+) -> Result<impl IntoResponse, AppError> {
+    let (limit, page) = pagination.limit_and_page();
+
+    let suppliers = state.search_suppliers(&filter, limit, page)
+        .await
+        .map_err(|err| AppError::unexpected(&err))?;
+
+    Ok(Json(AdminSupplierList { suppliers: suppliers.into_iter().map(AdminSupplier::from).collect() }))
+}
+
+async fn admin_get_supplier(
+    PathInput(SupplierPath { api_key }): PathInput<SupplierPath>,
+    State(state): State<Arc<AppState>>,
+) -> Result<impl IntoResponse, AppError> {
+    let supplier = admin_supplier(&state, &api_key).await?;
+    let goods = state.count_by_apikey(&api_key)
+        .await
+        .map_err(|err| AppError::unexpected(&err))?;
+
+    Ok(Json(AdminSupplierDetails { supplier: supplier.into(), goods }))
+}
+
+async fn admin_get_goods(
+    PathInput(SupplierPath { api_key }): PathInput<SupplierPath>,
+    ValidQuery(pagination): ValidQuery<Pagination>,
+    QueryInput(filter): QueryInput<GoodsFilter>,
+    State(state): State<Arc<AppState>>,
+) -> Result<impl IntoResponse, AppError> {
+    let supplier = admin_supplier(&state, &api_key).await?;
+    let (limit, page) = pagination.limit_and_page();
+
+    let goods = state.get_goods_status(&supplier.api_key, &filter, limit, page)
+        .await
+        .map_err(|err| AppError::unexpected(&err))?;
+    let goods = good_views(&state, &supplier, goods)
+        .await
+        .map_err(|err| AppError::unexpected(&err))?;
+
+    Ok(Json(GoodList { goods }))
+}
+
+async fn admin_get_runs(
+    PathInput(SupplierPath { api_key }): PathInput<SupplierPath>,
+    ValidQuery(pagination): ValidQuery<Pagination>,
+    State(state): State<Arc<AppState>>,
+) -> Result<impl IntoResponse, AppError> {
+    let supplier = admin_supplier(&state, &api_key).await?;
+    let (limit, page) = pagination.limit_and_page();
+
+    let runs = state.get_runs(&supplier.api_key, limit, page)
+        .await
+        .map_err(|err| AppError::unexpected(&err))?;
+
+    Ok(Json(RunList { runs }))
+}
+
+async fn set_supplier_disabled(state: &AppState, audit: Audit, api_key: &Uuid, disabled: bool) -> Result<Json<Ok>, AppError> {
+    let supplier = admin_supplier(state, api_key).await?;
+    if !state.set_supplier_disabled(api_key, disabled).await.map_err(|err| AppError::unexpected(&err))? {
+        return Err(AppError::NotFound(format!("Supplier {} not found", api_key)));
+    }
+    audit.for_supplier(*api_key)
+        .record("set_disabled", None, Some(json!(supplier.disabled)), Some(json!(disabled)))
+        .await;
+
+    Ok(Json(Ok { ok: true }))
+}
+
+async fn admin_disable_supplier(
+    PathInput(SupplierPath { api_key }): PathInput<SupplierPath>,
+    State(state): State<Arc<AppState>>,
+    audit: Audit,
+) -> Result<impl IntoResponse, AppError> {
+    set_supplier_disabled(&state, audit, &api_key, true).await
+}
+
+async fn admin_enable_supplier(
+    PathInput(SupplierPath { api_key }): PathInput<SupplierPath>,
+    State(state): State<Arc<AppState>>,
+    audit: Audit,
+) -> Result<impl IntoResponse, AppError> {
+    set_supplier_disabled(&state, audit, &api_key, false).await
+}
+
+async fn admin_delete_supplier(
+    PathInput(SupplierPath { api_key }): PathInput<SupplierPath>,
+    State(state): State<Arc<AppState>>,
+    audit: Audit,
+) -> Result<impl IntoResponse, AppError> {
+    let supplier = admin_supplier(&state, &api_key).await?;
+    if !state.delete_supplier(&api_key).await.map_err(|err| AppError::unexpected(&err))? {
+        return Err(AppError::NotFound(format!("Supplier {} not found", api_key)));
+    }
+    // the supplier's own audit entries are gone with it, so this one isn't tied to it
+    audit.record("delete_supplier", Some(api_key.to_string()), Some(json!(AdminSupplier::from(supplier))), None).await;
+
+    Ok(StatusCode::NO_CONTENT)
+}
+
+async fn admin_revoke_api_key(
+    PathInput(SupplierPath { api_key }): PathInput<SupplierPath>,
+    State(state): State<Arc<AppState>>,
+    audit: Audit,
+) -> Result<impl IntoResponse, AppError> {
+    admin_supplier(&state, &api_key).await?;
+    let revoked = state.revoke_api_keys(&api_key)
+        .await
+        .map_err(|err| AppError::unexpected(&err))?;
+    audit.for_supplier(api_key).record("revoke_api_keys", None, None, Some(json!(revoked))).await;
+
+    Ok(Json(Revoked { revoked }))
+}
+
+async fn admin_reissue_api_key(
+    PathInput(SupplierPath { api_key }): PathInput<SupplierPath>,
+    State(state): State<Arc<AppState>>,
+    audit: Audit,
+) -> Result<impl IntoResponse, AppError> {
+    admin_supplier(&state, &api_key).await?;
+    let issued = state.reissue_api_key(&api_key, "reissued")
+        .await
+        .map_err(|err| AppError::unexpected(&err))?;
+    audit.for_supplier(api_key)
+        .record("reissue_api_key", Some(issued.key.id.to_string()), None, Some(json!(issued.key)))
+        .await;
+
+    Ok(Json(issued))
+}
+
+async fn admin_set_plan(
+    PathInput(SupplierPath { api_key }): PathInput<SupplierPath>,
+    State(state): State<Arc<AppState>>,
+    audit: Audit,
+    ValidJson(input): ValidJson<PlanChange>,
+) -> Result<impl IntoResponse, AppError> {
+    change_plan(&state, audit, &api_key, &input.plan).await
+}
+
+async fn admin_sync(
+    PathInput(SupplierPath { api_key }): PathInput<SupplierPath>,
+    State(state): State<Arc<AppState>>,
+    audit: Audit,
+) -> Result<impl IntoResponse, AppError> {
+    let supplier = admin_supplier(&state, &api_key).await?;
+    state.request_sync(&supplier.api_key)
+        .map_err(|err| AppError::unexpected(&err))?;
+    audit.for_supplier(api_key).record("sync", None, None, None).await;
+
+    Ok((StatusCode::ACCEPTED, Json(Ok { ok: true })))
+}
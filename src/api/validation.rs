@@ -0,0 +1,186 @@
+use std::fmt;
+use axum::async_trait;
+use axum::extract::{FromRequest, FromRequestParts, Path, Query, Request};
+use axum::extract::path::ErrorKind;
+use axum::extract::rejection::PathRejection;
+use axum::http::request::Parts;
+use axum::Json;
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+use serde_json::Value;
+use crate::api::error::AppError;
+
+#[derive(Debug, Clone, Serialize)]
+pub struct Violation {
+    pub field: String,
+    pub message: String,
+}
+
+impl fmt::Display for Violation {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} {}", self.field, self.message)
+    }
+}
+
+#[derive(Default)]
+pub struct Violations(Vec<Violation>);
+
+impl Violations {
+    pub fn add(&mut self, field: &str, message: &str) {
+        self.0.push(Violation { field: field.to_string(), message: message.to_string() });
+    }
+
+    pub fn check(&mut self, valid: bool, field: &str, message: &str) {
+        if !valid {
+            self.add(field, message);
+        }
+    }
+
+    pub fn into_result(self) -> Result<(), AppError> {
+        if self.0.is_empty() {
+            Ok(())
+        } else {
+            Err(AppError::Validation(self.0))
+        }
+    }
+}
+
+/// Checks a request input and reports every problem at once, not just the first one.
+/// Inputs without rules beyond their types skip it and use the `*Input` extractors.
+pub trait Validate {
+    fn validate(&self, violations: &mut Violations);
+
+    fn check(&self) -> Result<(), AppError> {
+        let mut violations = Violations::default();
+        self.validate(&mut violations);
+        violations.into_result()
+    }
+}
+
+/// JSON body that deserialized, type errors point at the offending field.
+pub struct JsonInput<T>(pub T);
+
+#[async_trait]
+impl<T, S> FromRequest<S> for JsonInput<T>
+where
+    T: DeserializeOwned,
+    S: Send + Sync,
+{
+    type Rejection = AppError;
+
+    async fn from_request(request: Request, state: &S) -> Result<Self, Self::Rejection> {
+        let Json(value) = Json::<Value>::from_request(request, state)
+            .await
+            .map_err(|rejection| AppError::invalid("body", &rejection.body_text()))?;
+        let input: T = serde_path_to_error::deserialize(value).map_err(|err| {
+            let field = match err.path().to_string() {
+                path if path == "." => "body".to_string(),
+                path => path,
+            };
+            AppError::invalid(&field, &err.into_inner().to_string())
+        })?;
+
+        Ok(JsonInput(input))
+    }
+}
+
+/// JSON body that deserialized and passed `Validate`.
+pub struct ValidJson<T>(pub T);
+
+#[async_trait]
+impl<T, S> FromRequest<S> for ValidJson<T>
+where
+    T: DeserializeOwned + Validate,
+    S: Send + Sync,
+{
+    type Rejection = AppError;
+
+    async fn from_request(request: Request, state: &S) -> Result<Self, Self::Rejection> {
+        let JsonInput(input) = JsonInput::<T>::from_request(request, state).await?;
+        input.check()?;
+
+        Ok(ValidJson(input))
+    }
+}
+
+pub struct PathInput<T>(pub T);
+
+#[async_trait]
+impl<T, S> FromRequestParts<S> for PathInput<T>
+where
+    T: DeserializeOwned + Send,
+    S: Send + Sync,
+{
+    type Rejection = AppError;
+
+    async fn from_request_parts(parts: &mut Parts, state: &S) -> Result<Self, Self::Rejection> {
+        let Path(input) = Path::<T>::from_request_parts(parts, state)
+            .await
+            .map_err(|rejection| match rejection {
+                PathRejection::FailedToDeserializePathParams(err) => match err.into_kind() {
+                    ErrorKind::ParseErrorAtKey { key, expected_type, .. } => {
+                        AppError::invalid(&key, &format!("must be {}", expected_type))
+                    }
+                    kind => AppError::invalid("path", &kind.to_string()),
+                },
+                rejection => AppError::unexpected(&rejection.body_text()),
+            })?;
+
+        Ok(PathInput(input))
+    }
+}
+
+pub struct ValidPath<T>(pub T);
+
+#[async_trait]
+impl<T, S> FromRequestParts<S> for ValidPath<T>
+where
+    T: DeserializeOwned + Validate + Send,
+    S: Send + Sync,
+{
+    type Rejection = AppError;
+
+    async fn from_request_parts(parts: &mut Parts, state: &S) -> Result<Self, Self::Rejection> {
+        let PathInput(input) = PathInput::<T>::from_request_parts(parts, state).await?;
+        input.check()?;
+
+        Ok(ValidPath(input))
+    }
+}
+
+pub struct QueryInput<T>(pub T);
+
+#[async_trait]
+impl<T, S> FromRequestParts<S> for QueryInput<T>
+where
+    T: DeserializeOwned,
+    S: Send + Sync,
+{
+    type Rejection = AppError;
+
+    async fn from_request_parts(parts: &mut Parts, state: &S) -> Result<Self, Self::Rejection> {
+        let Query(input) = Query::<T>::from_request_parts(parts, state)
+            .await
+            .map_err(|rejection| AppError::invalid("query", &rejection.body_text()))?;
+
+        Ok(QueryInput(input))
+    }
+}
+
+pub struct ValidQuery<T>(pub T);
+
+#[async_trait]
+impl<T, S> FromRequestParts<S> for ValidQuery<T>
+where
+    T: DeserializeOwned + Validate,
+    S: Send + Sync,
+{
+    type Rejection = AppError;
+
+    async fn from_request_parts(parts: &mut Parts, state: &S) -> Result<Self, Self::Rejection> {
+        let QueryInput(input) = QueryInput::<T>::from_request_parts(parts, state).await?;
+        input.check()?;
+
+        Ok(ValidQuery(input))
+    }
+}
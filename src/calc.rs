@@ -18,6 +18,15 @@ pub fn count_new_basic(target_price: i32, current_discounted: i32, current_basic
     ((Decimal::from(new_price) * part).floor().to_i32().expect("smth wrong"), new_price)
 }
 
+pub fn count_spp(discounted: i32, total: i32) -> Option<Decimal> {
+    if discounted <= 0 {
+        return None;
+    }
+
+    let part = Decimal::from(total) / Decimal::from(discounted);
+    Some(((Decimal::ONE - part) * _d("100")).round_dp(2))
+}
+
 fn correct_part(start: Decimal) -> Decimal {
     let rounded_part_1000 = (start * _d("1000")).round();
     let last_digit = rounded_part_1000.to_i64().expect("smth wrong") % 10;
@@ -44,5 +53,5 @@ fn correct(base: i32, part: Decimal, target: i32, base_diff: i32) -> i32 {
 }
 
 fn _d(s: &str) -> Decimal {
-    return Decimal::from_str(s).expect("smth wrong")
+    Decimal::from_str(s).expect("smth wrong")
 }
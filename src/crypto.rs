@@ -0,0 +1,261 @@
+use std::collections::HashMap;
+use std::string::FromUtf8Error;
+use aes_gcm::{Aes256Gcm, Key, KeyInit, Nonce};
+use aes_gcm::aead::{Aead, AeadCore, OsRng};
+use base64::Engine;
+use base64::engine::general_purpose::STANDARD;
+use sha2::{Digest, Sha256};
+use subtle::ConstantTimeEq;
+use thiserror::Error;
+use uuid::Uuid;
+use crate::utils;
+
+const NONCE_LEN: usize = 12;
+
+#[derive(Error, Debug)]
+pub enum TokenError {
+    #[error("Unknown wb jwt key id {0}")]
+    UnknownKeyId(String),
+
+    #[error("Encrypted wb jwt is truncated")]
+    Truncated,
+
+    #[error("Failed encrypt wb jwt")]
+    Encrypt,
+
+    #[error("Failed decrypt wb jwt")]
+    Decrypt,
+
+    #[error("Failed decode wb jwt: {0}")]
+    Utf8(#[from] FromUtf8Error),
+}
+
+#[derive(Debug, Clone)]
+pub struct EncryptedToken {
+    pub ciphertext: Vec<u8>,
+    pub dek: Vec<u8>,
+    pub key_id: String,
+}
+
+pub struct TokenCipher {
+    keys: HashMap<String, Key<Aes256Gcm>>,
+    active_key_id: String,
+}
+
+impl TokenCipher {
+    // WB_JWT_KEYS="id1:base64key,id2:base64key", WB_JWT_KEY_ID selects the key for new tokens
+    pub fn from_env() -> Result<Self, String> {
+        Self::from_keys(&utils::get_env_var("WB_JWT_KEYS")?, &utils::get_env_var("WB_JWT_KEY_ID")?)
+    }
+
+    fn from_keys(entries: &str, active_key_id: &str) -> Result<Self, String> {
+        let mut keys = HashMap::new();
+        for entry in entries.split(',').map(str::trim).filter(|e| !e.is_empty()) {
+            let (id, key) = entry
+                .split_once(':')
+                .ok_or_else(|| format!("WB_JWT_KEYS entry {} must look like id:base64key", entry))?;
+            let key = STANDARD
+                .decode(key)
+                .map_err(|err| utils::make_err(Box::new(err), "decode WB_JWT_KEYS"))?;
+            if key.len() != 32 {
+                return Err(format!("WB_JWT_KEYS key {} must be 32 bytes", id));
+            }
+            keys.insert(id.to_string(), *Key::<Aes256Gcm>::from_slice(&key));
+        }
+
+        if !keys.contains_key(active_key_id) {
+            return Err(format!("WB_JWT_KEY_ID {} is not in WB_JWT_KEYS", active_key_id));
+        }
+
+        Ok(Self { keys, active_key_id: active_key_id.to_string() })
+    }
+
+    pub fn active_key_id(&self) -> &str {
+        &self.active_key_id
+    }
+
+    pub fn encrypt(&self, token: &str) -> Result<EncryptedToken, TokenError> {
+        let dek = Aes256Gcm::generate_key(OsRng);
+
+        Ok(EncryptedToken {
+            ciphertext: seal(&dek, token.as_bytes())?,
+            dek: seal(self.key(&self.active_key_id)?, &dek)?,
+            key_id: self.active_key_id.clone(),
+        })
+    }
+
+    pub fn decrypt(&self, token: &EncryptedToken) -> Result<String, TokenError> {
+        let dek = open(self.key(&token.key_id)?, &token.dek)?;
+        let plain = open(Key::<Aes256Gcm>::from_slice(&dek), &token.ciphertext)?;
+
+        Ok(String::from_utf8(plain)?)
+    }
+
+    pub fn rewrap(&self, token: &EncryptedToken) -> Result<EncryptedToken, TokenError> {
+        let dek = open(self.key(&token.key_id)?, &token.dek)?;
+
+        Ok(EncryptedToken {
+            ciphertext: token.ciphertext.clone(),
+            dek: seal(self.key(&self.active_key_id)?, &dek)?,
+            key_id: self.active_key_id.clone(),
+        })
+    }
+
+    fn key(&self, key_id: &str) -> Result<&Key<Aes256Gcm>, TokenError> {
+        self.keys
+            .get(key_id)
+            .ok_or_else(|| TokenError::UnknownKeyId(key_id.to_string()))
+    }
+}
+
+fn seal(key: &Key<Aes256Gcm>, plain: &[u8]) -> Result<Vec<u8>, TokenError> {
+    let nonce = Aes256Gcm::generate_nonce(&mut OsRng);
+    let sealed = Aes256Gcm::new(key)
+        .encrypt(&nonce, plain)
+        .map_err(|_| TokenError::Encrypt)?;
+
+    Ok([nonce.as_slice(), &sealed].concat())
+}
+
+fn open(key: &Key<Aes256Gcm>, sealed: &[u8]) -> Result<Vec<u8>, TokenError> {
+    if sealed.len() < NONCE_LEN {
+        return Err(TokenError::Truncated);
+    }
+    let (nonce, sealed) = sealed.split_at(NONCE_LEN);
+
+    Aes256Gcm::new(key)
+        .decrypt(Nonce::from_slice(nonce), sealed)
+        .map_err(|_| TokenError::Decrypt)
+}
+
+// api keys are random uuids, so a plain hash is enough to find them without storing them
+pub fn hash_api_key(key: &Uuid) -> Vec<u8> {
+    Sha256::digest(key.as_bytes()).to_vec()
+}
+
+pub struct AdminKeys {
+    keys: Vec<(String, [u8; 32])>,
+}
+
+impl AdminKeys {
+    // ADMIN_KEYS="name:key,..." and ADMIN_KEY_HASHES="name:hex(sha256(key)),..." replace SUPER_API_KEY when set
+    pub fn from_env() -> Result<Self, String> {
+        let mut keys = Vec::new();
+        for (name, key) in named_entries("ADMIN_KEYS")? {
+            keys.push((name, Sha256::digest(key.as_bytes()).into()));
+        }
+        for (name, hash) in named_entries("ADMIN_KEY_HASHES")? {
+            let hash = hex::decode(&hash)
+                .map_err(|err| utils::make_err(Box::new(err), "decode ADMIN_KEY_HASHES"))?
+                .try_into()
+                .map_err(|_| format!("ADMIN_KEY_HASHES hash of {} must be 32 bytes", name))?;
+            keys.push((name, hash));
+        }
+
+        if keys.is_empty() {
+            let key = utils::get_env_var("SUPER_API_KEY")
+                .map_err(|_| "SUPER_API_KEY, ADMIN_KEYS or ADMIN_KEY_HASHES must be set".to_string())?;
+            keys.push(("super".to_string(), Sha256::digest(key.as_bytes()).into()));
+        }
+
+        Ok(Self { keys })
+    }
+
+    /// Name of the admin the key belongs to. Every configured key is compared in constant time,
+    /// so the response time doesn't tell how close a guess was or which admin it matched.
+    pub fn verify(&self, key: &str) -> Option<&str> {
+        let hash = Sha256::digest(key.as_bytes());
+        let mut found = None;
+        for (name, expected) in &self.keys {
+            if bool::from(expected.ct_eq(hash.as_slice())) {
+                found = Some(name.as_str());
+            }
+        }
+
+        found
+    }
+}
+
+fn named_entries(var: &str) -> Result<Vec<(String, String)>, String> {
+    let Ok(value) = utils::get_env_var(var) else {
+        return Ok(Vec::new());
+    };
+
+    value.split(',')
+        .map(str::trim)
+        .filter(|e| !e.is_empty())
+        .map(|entry| {
+            entry
+                .split_once(':')
+                .filter(|(name, value)| !name.is_empty() && !value.is_empty())
+                .map(|(name, value)| (name.to_string(), value.to_string()))
+                .ok_or_else(|| format!("{} entry must look like name:value", var))
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const K1: &str = "k1:AAECAwQFBgcICQoLDA0ODxAREhMUFRYXGBkaGxwdHh8=";
+    const K2: &str = "k2:HxwdHh8AAQIDBAUGBwgJCgsMDQ4PEBESExQVFhcYGRo=";
+
+    fn cipher(active_key_id: &str) -> TokenCipher {
+        TokenCipher::from_keys(&format!("{},{}", K1, K2), active_key_id).unwrap()
+    }
+
+    #[test]
+    fn encrypt_then_decrypt_returns_token() {
+        let cipher = cipher("k1");
+
+        let token = cipher.encrypt("header.payload.signature").unwrap();
+
+        assert_eq!(token.key_id, "k1");
+        assert_ne!(token.ciphertext, b"header.payload.signature");
+        assert_eq!(cipher.decrypt(&token).unwrap(), "header.payload.signature");
+    }
+
+    #[test]
+    fn rewrap_moves_token_to_active_key() {
+        let token = cipher("k1").encrypt("jwt").unwrap();
+
+        let rewrapped = cipher("k2").rewrap(&token).unwrap();
+
+        assert_eq!(rewrapped.key_id, "k2");
+        assert_eq!(rewrapped.ciphertext, token.ciphertext);
+        assert_eq!(TokenCipher::from_keys(K2, "k2").unwrap().decrypt(&rewrapped).unwrap(), "jwt");
+    }
+
+    #[test]
+    fn rewrap_fails_for_unknown_key_id() {
+        let token = TokenCipher::from_keys(K2, "k2").unwrap().encrypt("jwt").unwrap();
+
+        let err = TokenCipher::from_keys(K1, "k1").unwrap().rewrap(&token).unwrap_err();
+
+        assert!(matches!(err, TokenError::UnknownKeyId(key_id) if key_id == "k2"));
+    }
+
+    #[test]
+    fn decrypt_fails_for_unknown_key_id() {
+        let token = cipher("k2").encrypt("jwt").unwrap();
+
+        let err = TokenCipher::from_keys(K1, "k1").unwrap().decrypt(&token).unwrap_err();
+
+        assert!(matches!(err, TokenError::UnknownKeyId(key_id) if key_id == "k2"));
+    }
+
+    #[test]
+    fn decrypt_fails_for_tampered_token() {
+        let cipher = cipher("k1");
+        let mut token = cipher.encrypt("jwt").unwrap();
+        *token.ciphertext.last_mut().unwrap() ^= 1;
+
+        assert!(matches!(cipher.decrypt(&token), Err(TokenError::Decrypt)));
+    }
+
+    #[test]
+    fn active_key_must_be_listed() {
+        assert!(TokenCipher::from_keys(K1, "k2").is_err());
+    }
+}
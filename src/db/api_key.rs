@@ -0,0 +1,150 @@
+use chrono::{DateTime, Utc};
+use serde::Serialize;
+use sqlx::{Error, PgExecutor, PgPool, types::Uuid};
+use crate::crypto::hash_api_key;
+
+#[derive(Debug, Serialize, Clone, sqlx::FromRow)]
+pub struct ApiKey {
+    pub id: i64,
+    pub label: String,
+    pub created_at: DateTime<Utc>,
+    pub last_used_at: Option<DateTime<Utc>>,
+    pub expires_at: Option<DateTime<Utc>>,
+    pub revoked_at: Option<DateTime<Utc>>,
+}
+
+/// The key a client authenticated with and the supplier it belongs to.
+#[derive(Debug, Clone)]
+pub struct KeyOwner {
+    pub id: i64,
+    pub supplier_api_key: Uuid,
+}
+
+/// A freshly created key, the only time its value is known.
+#[derive(Debug, Serialize)]
+pub struct IssuedApiKey {
+    pub api_key: Uuid,
+    #[serde(flatten)]
+    pub key: ApiKey,
+}
+
+impl ApiKey {
+    pub async fn create(
+        client: impl PgExecutor<'_>,
+        api_key: &Uuid,
+        label: &str,
+        expires_at: Option<DateTime<Utc>>,
+    ) -> Result<IssuedApiKey, Error> {
+        let value = Uuid::new_v4();
+        let key = sqlx::query_as!(
+            ApiKey,
+            r#"
+            INSERT INTO api_keys (supplier_api_key, key_hash, label, expires_at)
+            VALUES ($1, $2, $3, $4)
+            RETURNING id, label, created_at, last_used_at, expires_at, revoked_at
+            "#,
+            api_key,
+            hash_api_key(&value),
+            label,
+            expires_at
+        )
+            .fetch_one(client)
+            .await?;
+
+        Ok(IssuedApiKey { api_key: value, key })
+    }
+
+    /// Revokes every active key of the supplier and issues a new one in the same statement.
+    pub async fn reissue(client: &PgPool, api_key: &Uuid, label: &str) -> Result<IssuedApiKey, Error> {
+        let value = Uuid::new_v4();
+        let key = sqlx::query_as!(
+            ApiKey,
+            r#"
+            WITH revoked AS (
+                UPDATE api_keys SET revoked_at = NOW()
+                WHERE supplier_api_key = $1 AND revoked_at IS NULL
+            )
+            INSERT INTO api_keys (supplier_api_key, key_hash, label)
+            VALUES ($1, $2, $3)
+            RETURNING id, label, created_at, last_used_at, expires_at, revoked_at
+            "#,
+            api_key,
+            hash_api_key(&value),
+            label
+        )
+            .fetch_one(client)
+            .await?;
+
+        Ok(IssuedApiKey { api_key: value, key })
+    }
+
+    pub async fn list_by_apikey(client: &PgPool, api_key: &Uuid) -> Result<Vec<ApiKey>, Error> {
+        sqlx::query_as!(
+            ApiKey,
+            r#"
+            SELECT id, label, created_at, last_used_at, expires_at, revoked_at FROM api_keys
+            WHERE supplier_api_key = $1
+            ORDER BY created_at DESC
+            "#,
+            api_key
+        )
+            .fetch_all(client)
+            .await
+    }
+
+    /// Finds the supplier of a usable key. last_used_at is refreshed at most once a minute
+    /// so authenticating doesn't write on every request.
+    pub async fn authenticate(client: &PgPool, key: &Uuid) -> Result<Option<KeyOwner>, Error> {
+        sqlx::query_as!(
+            KeyOwner,
+            r#"
+            WITH found AS (
+                SELECT id, supplier_api_key FROM api_keys
+                WHERE key_hash = $1
+                  AND revoked_at IS NULL
+                  AND (expires_at IS NULL OR expires_at > NOW())
+            ), touched AS (
+                UPDATE api_keys SET last_used_at = NOW()
+                FROM found
+                WHERE api_keys.id = found.id
+                  AND (api_keys.last_used_at IS NULL OR api_keys.last_used_at < NOW() - INTERVAL '1 minute')
+            )
+            SELECT id, supplier_api_key FROM found
+            "#,
+            hash_api_key(key)
+        )
+            .fetch_optional(client)
+            .await
+    }
+
+    pub async fn revoke(client: &PgPool, api_key: &Uuid, id: i64) -> Result<(), Error> {
+        if sqlx::query!(
+            r#"
+            UPDATE api_keys SET revoked_at = NOW()
+            WHERE supplier_api_key = $1 AND id = $2 AND revoked_at IS NULL
+            "#,
+            api_key,
+            id
+        )
+            .execute(client)
+            .await?
+            .rows_affected() == 0 {
+            return Err(Error::RowNotFound)
+        }
+
+        Ok(())
+    }
+
+    pub async fn revoke_all(client: &PgPool, api_key: &Uuid) -> Result<u64, Error> {
+        sqlx::query!(
+            r#"
+            UPDATE api_keys SET revoked_at = NOW()
+            WHERE supplier_api_key = $1 AND revoked_at IS NULL
+            "#,
+            api_key
+        )
+            .execute(client)
+            .await
+            .map(|result| result.rows_affected())
+    }
+}
@@ -0,0 +1,69 @@
+use chrono::{DateTime, Utc};
+use serde::Serialize;
+use serde_json::Value;
+use sqlx::{Error, PgPool, types::Uuid};
+
+#[derive(Debug, Serialize, Clone, sqlx::FromRow)]
+pub struct AuditEntry {
+    pub id: i64,
+    pub actor: String,
+    pub api_key_id: Option<i64>,
+    pub action: String,
+    pub target: Option<String>,
+    pub old_value: Option<Value>,
+    pub new_value: Option<Value>,
+    pub client_ip: Option<String>,
+    pub created_at: DateTime<Utc>,
+}
+
+pub struct NewAuditEntry {
+    pub supplier_api_key: Option<Uuid>,
+    pub api_key_id: Option<i64>,
+    pub actor: String,
+    pub action: String,
+    pub target: Option<String>,
+    pub old_value: Option<Value>,
+    pub new_value: Option<Value>,
+    pub client_ip: Option<String>,
+}
+
+impl AuditEntry {
+    pub async fn create(client: &PgPool, entry: &NewAuditEntry) -> Result<(), Error> {
+        sqlx::query!(
+            r#"
+            INSERT INTO audit_log (supplier_api_key, api_key_id, actor, action, target, old_value, new_value, client_ip)
+            VALUES ($1, $2, $3, $4, $5, $6, $7, $8)
+            "#,
+            entry.supplier_api_key,
+            entry.api_key_id,
+            entry.actor,
+            entry.action,
+            entry.target,
+            entry.old_value,
+            entry.new_value,
+            entry.client_ip
+        )
+            .execute(client)
+            .await?;
+
+        Ok(())
+    }
+
+    pub async fn list_by_apikey(client: &PgPool, api_key: &Uuid, limit: i64, offset: i64) -> Result<Vec<AuditEntry>, Error> {
+        sqlx::query_as!(
+            AuditEntry,
+            r#"
+            SELECT id, actor, api_key_id, action, target, old_value, new_value, client_ip, created_at
+            FROM audit_log
+            WHERE supplier_api_key = $1
+            ORDER BY created_at DESC, id DESC
+            LIMIT $2 OFFSET $3
+            "#,
+            api_key,
+            limit,
+            offset
+        )
+            .fetch_all(client)
+            .await
+    }
+}
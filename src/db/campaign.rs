@@ -0,0 +1,152 @@
+use chrono::{DateTime, Utc};
+use serde::Serialize;
+use sqlx::{Error, PgPool, types::Uuid};
+use crate::db::product::Product;
+
+#[derive(Debug, Serialize, Clone, sqlx::FromRow)]
+pub struct Campaign {
+    pub id: i64,
+    pub name: String,
+    pub starts_at: DateTime<Utc>,
+    pub ends_at: DateTime<Utc>,
+    pub created_at: DateTime<Utc>,
+}
+
+impl Campaign {
+    pub async fn create(
+        client: &PgPool,
+        api_key: &Uuid,
+        name: &str,
+        starts_at: DateTime<Utc>,
+        ends_at: DateTime<Utc>,
+        products: &[Product],
+    ) -> Result<Campaign, Error> {
+        let mut transaction = client.begin().await?;
+
+        let ids: Vec<i32> = products.iter().map(|p| p.id).collect();
+        let owned = sqlx::query!(
+            r#"
+            SELECT COUNT(*) FROM products
+            WHERE supplier_api_key = $1 AND id = ANY($2)
+            "#,
+            api_key,
+            &ids
+        )
+            .fetch_one(&mut *transaction)
+            .await?
+            .count
+            .unwrap_or_default();
+        if owned != ids.len() as i64 {
+            return Err(Error::RowNotFound);
+        }
+
+        let campaign = sqlx::query_as!(
+            Campaign,
+            r#"
+            INSERT INTO campaigns (supplier_api_key, name, starts_at, ends_at)
+            VALUES ($1, $2, $3, $4)
+            RETURNING id, name, starts_at, ends_at, created_at
+            "#,
+            api_key,
+            name,
+            starts_at,
+            ends_at
+        )
+            .fetch_one(&mut *transaction)
+            .await?;
+
+        let prices: Vec<i32> = products.iter().map(|p| p.price).collect();
+        sqlx::query!(
+            r#"
+            INSERT INTO campaign_products (campaign_id, supplier_api_key, product_id, price)
+            SELECT $1, $2, product_id, price FROM UNNEST($3::INTEGER[], $4::INTEGER[]) AS input(product_id, price)
+            "#,
+            campaign.id,
+            api_key,
+            &ids,
+            &prices
+        )
+            .execute(&mut *transaction)
+            .await?;
+
+        transaction.commit().await?;
+        Ok(campaign)
+    }
+
+    pub async fn list_by_apikey(client: &PgPool, api_key: &Uuid) -> Result<Vec<Campaign>, Error> {
+        sqlx::query_as!(
+            Campaign,
+            r#"
+            SELECT id, name, starts_at, ends_at, created_at FROM campaigns
+            WHERE supplier_api_key = $1
+            ORDER BY starts_at DESC
+            "#,
+            api_key
+        )
+            .fetch_all(client)
+            .await
+    }
+
+    pub async fn get(client: &PgPool, api_key: &Uuid, id: i64) -> Result<Option<Campaign>, Error> {
+        sqlx::query_as!(
+            Campaign,
+            r#"
+            SELECT id, name, starts_at, ends_at, created_at FROM campaigns
+            WHERE supplier_api_key = $1 AND id = $2
+            "#,
+            api_key,
+            id
+        )
+            .fetch_optional(client)
+            .await
+    }
+
+    pub async fn get_products(client: &PgPool, id: i64) -> Result<Vec<Product>, Error> {
+        sqlx::query_as!(
+            Product,
+            r#"
+            SELECT product_id AS id, price FROM campaign_products
+            WHERE campaign_id = $1
+            ORDER BY product_id
+            "#,
+            id
+        )
+            .fetch_all(client)
+            .await
+    }
+
+    pub async fn get_active_prices(client: &PgPool, api_key: &Uuid, at: DateTime<Utc>) -> Result<Vec<Product>, Error> {
+        sqlx::query_as!(
+            Product,
+            r#"
+            SELECT DISTINCT ON (cp.product_id) cp.product_id AS id, cp.price
+            FROM campaign_products cp
+            JOIN campaigns c ON c.id = cp.campaign_id
+            WHERE c.supplier_api_key = $1 AND c.starts_at <= $2 AND c.ends_at > $2
+            ORDER BY cp.product_id, c.starts_at DESC
+            "#,
+            api_key,
+            at
+        )
+            .fetch_all(client)
+            .await
+    }
+
+    pub async fn delete(client: &PgPool, api_key: &Uuid, id: i64) -> Result<(), Error> {
+        if sqlx::query!(
+            r#"
+            DELETE FROM campaigns
+            WHERE supplier_api_key = $1 AND id = $2
+            "#,
+            api_key,
+            id
+        )
+            .execute(client)
+            .await?
+            .rows_affected() == 0 {
+            return Err(Error::RowNotFound)
+        }
+
+        Ok(())
+    }
+}
@@ -0,0 +1,85 @@
+use chrono::{DateTime, Utc};
+use serde::Serialize;
+use sqlx::{Error, PgPool, types::Uuid};
+
+#[derive(Debug, Serialize, Clone, sqlx::FromRow)]
+pub struct FreezeWindow {
+    pub id: i64,
+    pub starts_at: DateTime<Utc>,
+    pub ends_at: DateTime<Utc>,
+    pub reason: Option<String>,
+    pub created_at: DateTime<Utc>,
+}
+
+impl FreezeWindow {
+    pub async fn create(
+        client: &PgPool,
+        api_key: &Uuid,
+        starts_at: DateTime<Utc>,
+        ends_at: DateTime<Utc>,
+        reason: Option<&str>,
+    ) -> Result<FreezeWindow, Error> {
+        sqlx::query_as!(
+            FreezeWindow,
+            r#"
+            INSERT INTO freeze_windows (supplier_api_key, starts_at, ends_at, reason)
+            VALUES ($1, $2, $3, $4)
+            RETURNING id, starts_at, ends_at, reason, created_at
+            "#,
+            api_key,
+            starts_at,
+            ends_at,
+            reason
+        )
+            .fetch_one(client)
+            .await
+    }
+
+    pub async fn list_by_apikey(client: &PgPool, api_key: &Uuid) -> Result<Vec<FreezeWindow>, Error> {
+        sqlx::query_as!(
+            FreezeWindow,
+            r#"
+            SELECT id, starts_at, ends_at, reason, created_at FROM freeze_windows
+            WHERE supplier_api_key = $1
+            ORDER BY starts_at DESC
+            "#,
+            api_key
+        )
+            .fetch_all(client)
+            .await
+    }
+
+    pub async fn get_active(client: &PgPool, api_key: &Uuid, at: DateTime<Utc>) -> Result<Option<FreezeWindow>, Error> {
+        sqlx::query_as!(
+            FreezeWindow,
+            r#"
+            SELECT id, starts_at, ends_at, reason, created_at FROM freeze_windows
+            WHERE supplier_api_key = $1 AND starts_at <= $2 AND ends_at > $2
+            ORDER BY ends_at DESC
+            LIMIT 1
+            "#,
+            api_key,
+            at
+        )
+            .fetch_optional(client)
+            .await
+    }
+
+    pub async fn delete(client: &PgPool, api_key: &Uuid, id: i64) -> Result<(), Error> {
+        if sqlx::query!(
+            r#"
+            DELETE FROM freeze_windows
+            WHERE supplier_api_key = $1 AND id = $2
+            "#,
+            api_key,
+            id
+        )
+            .execute(client)
+            .await?
+            .rows_affected() == 0 {
+            return Err(Error::RowNotFound)
+        }
+
+        Ok(())
+    }
+}
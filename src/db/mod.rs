@@ -1,66 +1,518 @@
 pub mod supplier;
 pub mod product;
+pub mod run;
+pub mod planned_change;
+pub mod schedule;
+pub mod campaign;
+pub mod freeze;
+pub mod price_history;
+pub mod audit;
+pub mod plan;
+pub mod api_key;
 
-use sqlx::{Error, PgPool, types::Uuid};
-use sqlx::migrate::MigrateError;
-use crate::db::product::Product;
-use crate::db::supplier::Supplier;
-use crate::utils;
+use chrono::{DateTime, Utc};
+use sqlx::{PgPool, types::Uuid};
+use crate::db::product::{GoodSettings, GoodStatus, GoodsFilter, ImportedProduct, Limited, PriceBounds, Product, UpsertedProduct};
+use crate::db::run::Run;
+use crate::db::planned_change::PlannedChange;
+use crate::db::schedule::PriceWindow;
+use crate::db::campaign::Campaign;
+use crate::db::freeze::FreezeWindow;
+use crate::db::price_history::{HistoryRecord, ObservedPrice, PriceHistory, UploadedPrice};
+use crate::db::audit::{AuditEntry, NewAuditEntry};
+use crate::db::plan::Plan;
+use crate::db::api_key::{ApiKey, IssuedApiKey, KeyOwner};
+use crate::db::supplier::{Supplier, SupplierFilter};
+use crate::crypto::TokenCipher;
+use crate::error::Error;
+use crate::wb::{PriceChange, PriceUpdate};
+
+const DROP_PLAIN_WB_JWT_MIGRATION: i64 = 20241106090000;
 
 pub struct DB {
     client: PgPool,
+    cipher: TokenCipher,
 }
 
 impl DB {
-    pub async fn new(db_url: &str) -> Result<Self, String> {
+    pub async fn new(db_url: &str, cipher: TokenCipher) -> Result<Self, Error> {
         let client = PgPool::connect(db_url)
             .await
-            .map_err(|err| utils::make_err(Box::new(err), "get db client"))?;
+            .map_err(Error::db("get db client"))?;
+
+        Ok(Self { client, cipher })
+    }
+
+    /// Runs the migrations, encrypting plaintext wb jwts before the migration dropping their column.
+    /// Returns how many tokens were encrypted.
+    pub async fn run_migrations(&self) -> Result<u64, Error> {
+        let mut migrator = sqlx::migrate!("./migrations");
+        let migrations = migrator.migrations.clone();
+
+        migrator.migrations = migrations.iter()
+            .filter(|migration| migration.version < DROP_PLAIN_WB_JWT_MIGRATION)
+            .cloned()
+            .collect::<Vec<_>>()
+            .into();
+        migrator.set_ignore_missing(true);
+        migrator.run(&self.client).await?;
+
+        let encrypted = Supplier::encrypt_plain_wb_jwts(&self.client, &self.cipher)
+            .await
+            .map_err(Error::db("encrypt wb jwts"))?;
+
+        migrator.migrations = migrations;
+        migrator.set_ignore_missing(false);
+        migrator.run(&self.client).await?;
 
-        Ok(Self { client })
+        Ok(encrypted)
     }
 
-    pub async fn run_migrations(&self) -> Result<(), MigrateError> {
-        sqlx::migrate!("./migrations")
-            .run(&self.client)
+    pub async fn rewrap_wb_jwts(&self) -> Result<u64, Error> {
+        Supplier::rewrap_wb_jwts(&self.client, &self.cipher)
             .await
+            .map_err(Error::db("rewrap wb jwts"))
     }
 
     pub async fn get_supplier(&self, api_key: &Uuid) -> Result<Option<Supplier>, Error> {
-        Supplier::get(&self.client, api_key).await
+        Supplier::get(&self.client, &self.cipher, api_key)
+            .await
+            .map_err(Error::db("get supplier"))
     }
 
-    pub async fn create_supplier(&self) -> Result<Supplier, Error> {
-        Supplier::create(&self.client).await
+    pub async fn create_supplier(&self, label: &str) -> Result<(Supplier, IssuedApiKey), Error> {
+        Supplier::create(&self.client, &self.cipher, label)
+            .await
+            .map_err(Error::db("create supplier"))
     }
 
     pub async fn set_wb_jwt(&self, api_key: &Uuid, jwt: &str) -> Result<(), Error> {
-        Supplier::set_wb_jwt(&self.client, api_key, jwt).await
+        Supplier::set_wb_jwt(&self.client, &self.cipher, api_key, jwt)
+            .await
+            .map_err(Error::db("set wb_jwt"))
     }
 
     pub async fn set_wb_id(&self, api_key: &Uuid, wb_id: i32) -> Result<(), Error> {
-        Supplier::set_wb_id(&self.client, api_key, wb_id).await
+        Supplier::set_wb_id(&self.client, api_key, wb_id)
+            .await
+            .map_err(Error::db("set wb id"))
+    }
+
+    pub async fn set_dry_run(&self, api_key: &Uuid, dry_run: bool) -> Result<(), Error> {
+        Supplier::set_dry_run(&self.client, api_key, dry_run)
+            .await
+            .map_err(Error::db("set dry run"))
+    }
+
+    pub async fn set_supplier_active(&self, api_key: &Uuid, active: bool) -> Result<(), Error> {
+        Supplier::set_active(&self.client, api_key, active)
+            .await
+            .map_err(Error::db("set supplier active"))
+    }
+
+    pub async fn set_timezone(&self, api_key: &Uuid, timezone: &str) -> Result<(), Error> {
+        Supplier::set_timezone(&self.client, api_key, timezone)
+            .await
+            .map_err(Error::db("set timezone"))
+    }
+
+    pub async fn set_plan(&self, api_key: &Uuid, plan: &str) -> Result<(), Error> {
+        Supplier::set_plan(&self.client, api_key, plan)
+            .await
+            .map_err(Error::db("set plan"))
+    }
+
+    pub async fn get_plan(&self, id: &str) -> Result<Option<Plan>, Error> {
+        Plan::get(&self.client, id)
+            .await
+            .map_err(Error::db("get plan"))
+    }
+
+    pub async fn register_auth_failure(&self, api_key: &Uuid, limit: i32) -> Result<bool, Error> {
+        Supplier::register_auth_failure(&self.client, api_key, limit)
+            .await
+            .map_err(Error::db("register auth failure"))
+    }
+
+    pub async fn reset_auth_failures(&self, api_key: &Uuid) -> Result<(), Error> {
+        Supplier::reset_auth_failures(&self.client, api_key)
+            .await
+            .map_err(Error::db("reset auth failures"))
     }
 
-    pub async fn add_goods(&self, api_key: &Uuid, products: &Vec<Product>) -> Result<(), Error> {
-        Product::create_many(&self.client, api_key, products).await
+    pub async fn add_goods(&self, api_key: &Uuid, products: &[Product], plan: &Plan) -> Result<Vec<UpsertedProduct>, Error> {
+        Product::create_many(&self.client, api_key, products, plan.max_products)
+            .await
+            .map_err(Error::db("add goods"))
+            .and_then(|limited| within_plan(limited, plan))
     }
 
-    pub async fn get_suppliers(&self, limit: usize, page: usize) -> Result<Vec<Supplier>, Error> {
+    pub async fn get_suppliers(&self, limit: usize, page: usize) -> Result<Vec<Result<Supplier, Error>>, Error> {
         let offset = (page - 1) * limit;
 
-        Supplier::list(&self.client, limit as i64, offset as i64).await
+        Supplier::list(&self.client, &self.cipher, limit as i64, offset as i64)
+            .await
+            .map_err(Error::db("get suppliers"))
+            .map(|suppliers| {
+                suppliers
+                    .into_iter()
+                    .map(|supplier| supplier.map_err(Error::db("decrypt supplier")))
+                    .collect()
+            })
+    }
+
+    pub async fn search_suppliers(&self, filter: &SupplierFilter, limit: usize, page: usize) -> Result<Vec<Supplier>, Error> {
+        let offset = (page - 1) * limit;
+
+        Supplier::search(&self.client, &self.cipher, filter, limit as i64, offset as i64)
+            .await
+            .map_err(Error::db("search suppliers"))
+    }
+
+    pub async fn set_supplier_disabled(&self, api_key: &Uuid, disabled: bool) -> Result<(), Error> {
+        Supplier::set_disabled(&self.client, api_key, disabled)
+            .await
+            .map_err(Error::db("set supplier disabled"))
+    }
+
+    pub async fn create_api_key(
+        &self,
+        api_key: &Uuid,
+        label: &str,
+        expires_at: Option<DateTime<Utc>>,
+    ) -> Result<IssuedApiKey, Error> {
+        ApiKey::create(&self.client, api_key, label, expires_at)
+            .await
+            .map_err(Error::db("create api key"))
+    }
+
+    pub async fn reissue_api_key(&self, api_key: &Uuid, label: &str) -> Result<IssuedApiKey, Error> {
+        ApiKey::reissue(&self.client, api_key, label)
+            .await
+            .map_err(Error::db("reissue api key"))
+    }
+
+    pub async fn get_api_keys(&self, api_key: &Uuid) -> Result<Vec<ApiKey>, Error> {
+        ApiKey::list_by_apikey(&self.client, api_key)
+            .await
+            .map_err(Error::db("get api keys"))
+    }
+
+    pub async fn authenticate(&self, key: &Uuid) -> Result<Option<KeyOwner>, Error> {
+        ApiKey::authenticate(&self.client, key)
+            .await
+            .map_err(Error::db("authenticate api key"))
+    }
+
+    pub async fn revoke_api_key(&self, api_key: &Uuid, id: i64) -> Result<(), Error> {
+        ApiKey::revoke(&self.client, api_key, id)
+            .await
+            .map_err(Error::db("revoke api key"))
+    }
+
+    pub async fn revoke_api_keys(&self, api_key: &Uuid) -> Result<u64, Error> {
+        ApiKey::revoke_all(&self.client, api_key)
+            .await
+            .map_err(Error::db("revoke api keys"))
+    }
+
+    pub async fn delete_supplier(&self, api_key: &Uuid) -> Result<(), Error> {
+        Supplier::delete(&self.client, api_key)
+            .await
+            .map_err(Error::db("delete supplier"))
+    }
+
+    pub async fn get_goods(&self, api_key: &Uuid, active: Option<bool>) -> Result<Vec<Product>, Error> {
+        Product::get_by_apikey(&self.client, api_key, active)
+            .await
+            .map_err(Error::db("get goods"))
+    }
+
+    pub async fn set_goods_active(&self, api_key: &Uuid, ids: Option<&[i32]>, active: bool) -> Result<u64, Error> {
+        Product::set_active(&self.client, api_key, ids, active)
+            .await
+            .map_err(Error::db("set goods active"))
+    }
+
+    pub async fn get_good(&self, api_key: &Uuid, id: i32) -> Result<Option<Product>, Error> {
+        Product::get(&self.client, api_key, id)
+            .await
+            .map_err(Error::db("get good"))
+    }
+
+    pub async fn get_good_settings(&self, api_key: &Uuid, id: i32) -> Result<Option<GoodSettings>, Error> {
+        Product::get_settings(&self.client, api_key, id)
+            .await
+            .map_err(Error::db("get good settings"))
+    }
+
+    pub async fn save_good_settings(&self, api_key: &Uuid, good: &GoodSettings, plan: &Plan) -> Result<Option<GoodSettings>, Error> {
+        Product::save_settings(&self.client, api_key, good, plan.max_products)
+            .await
+            .map_err(Error::db("save good settings"))
+            .and_then(|limited| within_plan(limited, plan))
     }
 
-    pub async fn get_goods(&self, api_key: &Uuid) -> Result<Vec<Product>, Error> {
-        Product::get_by_apikey(&self.client, api_key).await
+    pub async fn update_good_settings(&self, api_key: &Uuid, old: &GoodSettings, good: &GoodSettings) -> Result<(), Error> {
+        Product::update_settings(&self.client, api_key, old, good)
+            .await
+            .map_err(Error::db("update good settings"))
     }
 
     pub async fn count_by_apikey(&self, api_key: &Uuid) -> Result<i64, Error> {
-        Product::count_by_apikey(&self.client, api_key).await
+        Product::count_by_apikey(&self.client, api_key)
+            .await
+            .map_err(Error::db("count by apikey"))
     }
 
     pub async fn delete_by_id_and_api_key(&self, id: i32, api_key: &Uuid) -> Result<(), Error> {
-        Product::delete_by_id_and_api_key(&self.client, id, api_key).await
+        Product::delete_by_id_and_api_key(&self.client, id, api_key)
+            .await
+            .map_err(Error::db("delete by id and apikey"))
+    }
+
+    pub async fn start_run(
+        &self,
+        parent_id: Option<i64>,
+        api_key: Option<&Uuid>,
+        dry_run: bool,
+        frozen: bool,
+    ) -> Result<i64, Error> {
+        Run::start(&self.client, parent_id, api_key, dry_run, frozen)
+            .await
+            .map_err(Error::db("start run"))
+    }
+
+    pub async fn finish_run(
+        &self,
+        id: i64,
+        products_checked: i32,
+        products_changed: i32,
+        error_class: Option<&str>,
+        error_message: Option<&str>,
+    ) -> Result<(), Error> {
+        Run::finish(&self.client, id, products_checked, products_changed, error_class, error_message)
+            .await
+            .map_err(Error::db("finish run"))
+    }
+
+    pub async fn get_goods_status(&self, api_key: &Uuid, filter: &GoodsFilter, limit: usize, page: usize) -> Result<Vec<GoodStatus>, Error> {
+        let offset = (page - 1) * limit;
+
+        Product::list_status_by_apikey(&self.client, api_key, filter, limit as i64, offset as i64)
+            .await
+            .map_err(Error::db("get goods status"))
+    }
+
+    pub async fn set_goods_errors(&self, api_key: &Uuid, update: &PriceUpdate) -> Result<(), Error> {
+        let observed: Vec<i32> = update.observed.iter().map(|p| p.id).collect();
+
+        Product::set_errors(&self.client, api_key, &update.missing, &observed)
+            .await
+            .map_err(Error::db("set goods errors"))
+    }
+
+    pub async fn import_goods(&self, api_key: &Uuid, products: &[ImportedProduct], plan: &Plan) -> Result<Vec<UpsertedProduct>, Error> {
+        Product::import_many(&self.client, api_key, products, plan.max_products)
+            .await
+            .map_err(Error::db("import goods"))
+            .and_then(|limited| within_plan(limited, plan))
+    }
+
+    pub async fn find_goods_by_vendor_codes(&self, api_key: &Uuid, vendor_codes: &[String]) -> Result<Vec<(String, i32)>, Error> {
+        Product::find_by_vendor_codes(&self.client, api_key, vendor_codes)
+            .await
+            .map_err(Error::db("find goods by vendor codes"))
+    }
+
+    pub async fn get_price_bounds(&self, api_key: &Uuid) -> Result<Vec<PriceBounds>, Error> {
+        Product::get_bounds_by_apikey(&self.client, api_key)
+            .await
+            .map_err(Error::db("get price bounds"))
+    }
+
+
+    pub async fn get_last_run_started_at(&self, api_key: &Uuid) -> Result<Option<DateTime<Utc>>, Error> {
+        Run::last_started_at(&self.client, api_key)
+            .await
+            .map_err(Error::db("get last run started at"))
+    }
+
+    pub async fn get_runs(&self, api_key: &Uuid, limit: usize, page: usize) -> Result<Vec<Run>, Error> {
+        let offset = (page - 1) * limit;
+
+        Run::list_by_apikey(&self.client, api_key, limit as i64, offset as i64)
+            .await
+            .map_err(Error::db("get runs"))
+    }
+
+    pub async fn add_planned_changes(&self, api_key: &Uuid, run_id: Option<i64>, frozen: bool, changes: &[PriceChange]) -> Result<(), Error> {
+        PlannedChange::create_many(&self.client, api_key, run_id, frozen, changes)
+            .await
+            .map_err(Error::db("add planned changes"))
+    }
+
+    pub async fn get_planned_changes(&self, api_key: &Uuid, frozen: bool, limit: usize, page: usize) -> Result<Vec<PlannedChange>, Error> {
+        let offset = (page - 1) * limit;
+
+        PlannedChange::list_by_apikey(&self.client, api_key, frozen, limit as i64, offset as i64)
+            .await
+            .map_err(Error::db("get planned changes"))
+    }
+
+    pub async fn get_schedules(&self, api_key: &Uuid) -> Result<Vec<PriceWindow>, Error> {
+        PriceWindow::get_by_apikey(&self.client, api_key)
+            .await
+            .map_err(Error::db("get schedules"))
+    }
+
+    pub async fn get_schedule(&self, api_key: &Uuid, product_id: i32) -> Result<Vec<PriceWindow>, Error> {
+        PriceWindow::get_by_product(&self.client, api_key, product_id)
+            .await
+            .map_err(Error::db("get schedule"))
+    }
+
+    pub async fn set_schedule(&self, api_key: &Uuid, product_id: i32, windows: &[PriceWindow]) -> Result<(), Error> {
+        PriceWindow::replace_for_product(&self.client, api_key, product_id, windows)
+            .await
+            .map_err(Error::db("set schedule"))
+    }
+
+    pub async fn create_campaign(
+        &self,
+        api_key: &Uuid,
+        name: &str,
+        starts_at: DateTime<Utc>,
+        ends_at: DateTime<Utc>,
+        products: &[Product],
+    ) -> Result<Campaign, Error> {
+        Campaign::create(&self.client, api_key, name, starts_at, ends_at, products)
+            .await
+            .map_err(Error::db("create campaign"))
+    }
+
+    pub async fn get_campaigns(&self, api_key: &Uuid) -> Result<Vec<Campaign>, Error> {
+        Campaign::list_by_apikey(&self.client, api_key)
+            .await
+            .map_err(Error::db("get campaigns"))
     }
-}
\ No newline at end of file
+
+    pub async fn get_campaign(&self, api_key: &Uuid, id: i64) -> Result<Option<(Campaign, Vec<Product>)>, Error> {
+        match Campaign::get(&self.client, api_key, id).await.map_err(Error::db("get campaign"))? {
+            Some(campaign) => {
+                let products = Campaign::get_products(&self.client, campaign.id)
+                    .await
+                    .map_err(Error::db("get campaign products"))?;
+                Ok(Some((campaign, products)))
+            }
+            None => Ok(None),
+        }
+    }
+
+    pub async fn get_campaign_prices(&self, api_key: &Uuid, at: DateTime<Utc>) -> Result<Vec<Product>, Error> {
+        Campaign::get_active_prices(&self.client, api_key, at)
+            .await
+            .map_err(Error::db("get campaign prices"))
+    }
+
+    pub async fn delete_campaign(&self, api_key: &Uuid, id: i64) -> Result<(), Error> {
+        Campaign::delete(&self.client, api_key, id)
+            .await
+            .map_err(Error::db("delete campaign"))
+    }
+
+    pub async fn create_freeze(
+        &self,
+        api_key: &Uuid,
+        starts_at: DateTime<Utc>,
+        ends_at: DateTime<Utc>,
+        reason: Option<&str>,
+    ) -> Result<FreezeWindow, Error> {
+        FreezeWindow::create(&self.client, api_key, starts_at, ends_at, reason)
+            .await
+            .map_err(Error::db("create freeze"))
+    }
+
+    pub async fn get_freezes(&self, api_key: &Uuid) -> Result<Vec<FreezeWindow>, Error> {
+        FreezeWindow::list_by_apikey(&self.client, api_key)
+            .await
+            .map_err(Error::db("get freezes"))
+    }
+
+    pub async fn get_active_freeze(&self, api_key: &Uuid, at: DateTime<Utc>) -> Result<Option<FreezeWindow>, Error> {
+        FreezeWindow::get_active(&self.client, api_key, at)
+            .await
+            .map_err(Error::db("get active freeze"))
+    }
+
+    pub async fn delete_freeze(&self, api_key: &Uuid, id: i64) -> Result<(), Error> {
+        FreezeWindow::delete(&self.client, api_key, id)
+            .await
+            .map_err(Error::db("delete freeze"))
+    }
+
+    pub async fn record_price_history(&self, api_key: &Uuid, update: &PriceUpdate) -> Result<(), Error> {
+        PriceHistory::record(&self.client, api_key, update)
+            .await
+            .map_err(Error::db("record price history"))
+    }
+
+    pub async fn get_price_history(
+        &self,
+        api_key: &Uuid,
+        product_id: i32,
+        from: DateTime<Utc>,
+        to: DateTime<Utc>,
+        bucket_secs: Option<i64>,
+    ) -> Result<(Vec<ObservedPrice>, Vec<UploadedPrice>), Error> {
+        let observed = PriceHistory::get_observed(&self.client, api_key, product_id, from, to, bucket_secs)
+            .await
+            .map_err(Error::db("get observed prices"))?;
+        let uploaded = PriceHistory::get_uploaded(&self.client, api_key, product_id, from, to)
+            .await
+            .map_err(Error::db("get uploaded prices"))?;
+
+        Ok((observed, uploaded))
+    }
+
+    pub async fn get_history_after(
+        &self,
+        api_key: &Uuid,
+        product_id: Option<i32>,
+        from: DateTime<Utc>,
+        to: DateTime<Utc>,
+        after_id: i64,
+        limit: usize,
+    ) -> Result<Vec<HistoryRecord>, Error> {
+        PriceHistory::list_after(&self.client, api_key, product_id, from, to, after_id, limit as i64)
+            .await
+            .map_err(Error::db("get history after"))
+    }
+
+    pub async fn prune_price_history(&self, before: DateTime<Utc>) -> Result<u64, Error> {
+        PriceHistory::delete_older_than(&self.client, before)
+            .await
+            .map_err(Error::db("prune price history"))
+    }
+
+    pub async fn add_audit_entry(&self, entry: &NewAuditEntry) -> Result<(), Error> {
+        AuditEntry::create(&self.client, entry)
+            .await
+            .map_err(Error::db("add audit entry"))
+    }
+
+    pub async fn get_audit_log(&self, api_key: &Uuid, limit: usize, page: usize) -> Result<Vec<AuditEntry>, Error> {
+        let offset = (page - 1) * limit;
+
+        AuditEntry::list_by_apikey(&self.client, api_key, limit as i64, offset as i64)
+            .await
+            .map_err(Error::db("get audit log"))
+    }
+}
+
+fn within_plan<T>(limited: Limited<T>, plan: &Plan) -> Result<T, Error> {
+    match limited {
+        Limited::Within(value) => Ok(value),
+        Limited::Exceeded(total) => Err(Error::GoodsLimit { plan: plan.id.clone(), max: plan.max_products, total }),
+    }
+}
@@ -0,0 +1,41 @@
+use serde::Serialize;
+use sqlx::{Error, PgPool};
+
+#[derive(Debug, Serialize, Clone, sqlx::FromRow)]
+pub struct Plan {
+    pub id: String,
+    pub max_products: i32,
+    /// seconds between background updates of one supplier
+    pub min_update_interval: i32,
+    /// requests a supplier can make to the api per minute, across all its keys
+    pub requests_per_minute: i32,
+    /// calls of /update_price per minute, each one is a call to WB
+    pub price_updates_per_minute: i32,
+    pub features: Vec<String>,
+}
+
+impl Plan {
+    pub const DRY_RUN: &'static str = "dry_run";
+    pub const SCHEDULES: &'static str = "schedules";
+    pub const CAMPAIGNS: &'static str = "campaigns";
+    pub const FREEZES: &'static str = "freezes";
+    pub const BULK_GOODS: &'static str = "bulk_goods";
+
+    pub fn allows(&self, feature: &str) -> bool {
+        self.features.iter().any(|f| f == feature)
+    }
+
+    pub async fn get(client: &PgPool, id: &str) -> Result<Option<Plan>, Error> {
+        sqlx::query_as!(
+            Plan,
+            r#"
+            SELECT id, max_products, min_update_interval, requests_per_minute, price_updates_per_minute, features
+            FROM plans
+            WHERE id = $1
+            "#,
+            id
+        )
+            .fetch_optional(client)
+            .await
+    }
+}
@@ -0,0 +1,81 @@
+use chrono::{DateTime, Utc};
+use serde::Serialize;
+use sqlx::{Error, PgPool, types::Uuid};
+use crate::wb::PriceChange;
+
+#[derive(Debug, Serialize, Clone, sqlx::FromRow)]
+pub struct PlannedChange {
+    pub id: i64,
+    pub run_id: Option<i64>,
+    pub product_id: i32,
+    pub target_price: i32,
+    pub observed_basic: i32,
+    pub observed_total: i32,
+    pub new_price: i32,
+    pub frozen: bool,
+    pub created_at: DateTime<Utc>,
+}
+
+impl PlannedChange {
+    pub async fn create_many(
+        client: &PgPool,
+        api_key: &Uuid,
+        run_id: Option<i64>,
+        frozen: bool,
+        changes: &[PriceChange],
+    ) -> Result<(), Error> {
+        let ids: Vec<i32> = changes.iter().map(|c| c.id).collect();
+        let target_prices: Vec<i32> = changes.iter().map(|c| c.target_price).collect();
+        let observed_basics: Vec<i32> = changes.iter().map(|c| c.observed_basic).collect();
+        let observed_totals: Vec<i32> = changes.iter().map(|c| c.observed_total).collect();
+        let new_prices: Vec<i32> = changes.iter().map(|c| c.new_price).collect();
+
+        sqlx::query!(
+            r#"
+            INSERT INTO planned_price_changes
+                (run_id, supplier_api_key, product_id, target_price, observed_basic, observed_total, new_price, frozen)
+            SELECT $1, $2, product_id, target_price, observed_basic, observed_total, new_price, $3
+            FROM UNNEST($4::INTEGER[], $5::INTEGER[], $6::INTEGER[], $7::INTEGER[], $8::INTEGER[])
+                AS input(product_id, target_price, observed_basic, observed_total, new_price)
+            "#,
+            run_id,
+            api_key,
+            frozen,
+            &ids,
+            &target_prices,
+            &observed_basics,
+            &observed_totals,
+            &new_prices
+        )
+            .execute(client)
+            .await?;
+
+        Ok(())
+    }
+
+    /// Dry run previews and drift observed during a freeze are listed separately.
+    pub async fn list_by_apikey(
+        client: &PgPool,
+        api_key: &Uuid,
+        frozen: bool,
+        limit: i64,
+        offset: i64,
+    ) -> Result<Vec<PlannedChange>, Error> {
+        sqlx::query_as!(
+            PlannedChange,
+            r#"
+            SELECT id, run_id, product_id, target_price, observed_basic, observed_total, new_price, frozen, created_at
+            FROM planned_price_changes
+            WHERE supplier_api_key = $1 AND frozen = $2
+            ORDER BY created_at DESC, id DESC
+            LIMIT $3 OFFSET $4
+            "#,
+            api_key,
+            frozen,
+            limit,
+            offset
+        )
+            .fetch_all(client)
+            .await
+    }
+}
@@ -0,0 +1,200 @@
+use chrono::{DateTime, Utc};
+use rust_decimal::prelude::ToPrimitive;
+use serde::Serialize;
+use sqlx::{Error, PgPool, types::Uuid};
+use crate::calc::count_spp;
+use crate::wb::PriceUpdate;
+
+#[derive(Debug, Serialize, Clone, sqlx::FromRow)]
+pub struct ObservedPrice {
+    pub at: DateTime<Utc>,
+    pub basic: Option<i32>,
+    pub total: Option<i32>,
+    pub spp: Option<f64>,
+}
+
+#[derive(Debug, Serialize, Clone, sqlx::FromRow)]
+pub struct UploadedPrice {
+    pub at: DateTime<Utc>,
+    pub price: Option<i32>,
+}
+
+#[derive(Debug, Serialize, Clone, sqlx::FromRow)]
+pub struct HistoryRecord {
+    pub id: i64,
+    pub product_id: i32,
+    pub kind: String,
+    pub basic: Option<i32>,
+    pub total: Option<i32>,
+    pub spp: Option<f64>,
+    pub price: Option<i32>,
+    pub recorded_at: DateTime<Utc>,
+}
+
+pub struct PriceHistory;
+
+impl PriceHistory {
+    pub async fn record(client: &PgPool, api_key: &Uuid, update: &PriceUpdate) -> Result<(), Error> {
+        let mut transaction = client.begin().await?;
+
+        let ids: Vec<i32> = update.observed.iter().map(|p| p.id).collect();
+        let basics: Vec<i32> = update.observed.iter().map(|p| p.basic).collect();
+        let totals: Vec<i32> = update.observed.iter().map(|p| p.total).collect();
+        let spps: Vec<Option<f64>> = update.observed
+            .iter()
+            .map(|p| count_spp(p.product.unwrap_or(p.basic), p.total).and_then(|spp| spp.to_f64()))
+            .collect();
+
+        sqlx::query!(
+            r#"
+            INSERT INTO price_history (supplier_api_key, product_id, kind, basic, total, spp)
+            SELECT $1, id, 'observed', basic, total, spp::NUMERIC(5, 2)
+            FROM UNNEST($2::INTEGER[], $3::INTEGER[], $4::INTEGER[], $5::FLOAT8[]) AS t(id, basic, total, spp)
+            "#,
+            api_key,
+            &ids,
+            &basics,
+            &totals,
+            &spps as &[Option<f64>]
+        )
+            .execute(&mut *transaction)
+            .await?;
+
+        if update.uploaded {
+            let ids: Vec<i32> = update.changes.iter().map(|c| c.id).collect();
+            let prices: Vec<i32> = update.changes.iter().map(|c| c.new_price).collect();
+
+            sqlx::query!(
+                r#"
+                INSERT INTO price_history (supplier_api_key, product_id, kind, price)
+                SELECT $1, id, 'uploaded', price
+                FROM UNNEST($2::INTEGER[], $3::INTEGER[]) AS t(id, price)
+                "#,
+                api_key,
+                &ids,
+                &prices
+            )
+                .execute(&mut *transaction)
+                .await?;
+        }
+
+        transaction.commit().await?;
+        Ok(())
+    }
+
+    pub async fn get_observed(
+        client: &PgPool,
+        api_key: &Uuid,
+        product_id: i32,
+        from: DateTime<Utc>,
+        to: DateTime<Utc>,
+        bucket_secs: Option<i64>,
+    ) -> Result<Vec<ObservedPrice>, Error> {
+        match bucket_secs {
+            None => sqlx::query_as!(
+                ObservedPrice,
+                r#"
+                SELECT recorded_at AS at, basic, total, spp::FLOAT8 AS spp
+                FROM price_history
+                WHERE supplier_api_key = $1 AND product_id = $2 AND kind = 'observed'
+                    AND recorded_at >= $3 AND recorded_at < $4
+                ORDER BY recorded_at
+                "#,
+                api_key,
+                product_id,
+                from,
+                to
+            )
+                .fetch_all(client)
+                .await,
+            Some(bucket_secs) => sqlx::query_as!(
+                ObservedPrice,
+                r#"
+                SELECT date_bin(make_interval(secs => $5), recorded_at, TIMESTAMPTZ 'epoch') AS "at!",
+                       ROUND(AVG(basic))::INTEGER AS basic,
+                       ROUND(AVG(total))::INTEGER AS total,
+                       ROUND(AVG(spp), 2)::FLOAT8 AS spp
+                FROM price_history
+                WHERE supplier_api_key = $1 AND product_id = $2 AND kind = 'observed'
+                    AND recorded_at >= $3 AND recorded_at < $4
+                GROUP BY 1
+                ORDER BY 1
+                "#,
+                api_key,
+                product_id,
+                from,
+                to,
+                bucket_secs as f64
+            )
+                .fetch_all(client)
+                .await,
+        }
+    }
+
+    pub async fn get_uploaded(
+        client: &PgPool,
+        api_key: &Uuid,
+        product_id: i32,
+        from: DateTime<Utc>,
+        to: DateTime<Utc>,
+    ) -> Result<Vec<UploadedPrice>, Error> {
+        sqlx::query_as!(
+            UploadedPrice,
+            r#"
+            SELECT recorded_at AS at, price
+            FROM price_history
+            WHERE supplier_api_key = $1 AND product_id = $2 AND kind = 'uploaded'
+                AND recorded_at >= $3 AND recorded_at < $4
+            ORDER BY recorded_at
+            "#,
+            api_key,
+            product_id,
+            from,
+            to
+        )
+            .fetch_all(client)
+            .await
+    }
+
+    pub async fn list_after(
+        client: &PgPool,
+        api_key: &Uuid,
+        product_id: Option<i32>,
+        from: DateTime<Utc>,
+        to: DateTime<Utc>,
+        after_id: i64,
+        limit: i64,
+    ) -> Result<Vec<HistoryRecord>, Error> {
+        sqlx::query_as!(
+            HistoryRecord,
+            r#"
+            SELECT id, product_id, kind, basic, total, spp::FLOAT8 AS spp, price, recorded_at
+            FROM price_history
+            WHERE supplier_api_key = $1 AND ($2::INTEGER IS NULL OR product_id = $2)
+                AND recorded_at >= $3 AND recorded_at < $4 AND id > $5
+            ORDER BY id
+            LIMIT $6
+            "#,
+            api_key,
+            product_id,
+            from,
+            to,
+            after_id,
+            limit
+        )
+            .fetch_all(client)
+            .await
+    }
+
+    pub async fn delete_older_than(client: &PgPool, before: DateTime<Utc>) -> Result<u64, Error> {
+        sqlx::query!(
+            r#"
+            DELETE FROM price_history WHERE recorded_at < $1
+            "#,
+            before
+        )
+            .execute(client)
+            .await
+            .map(|result| result.rows_affected())
+    }
+}
@@ -1,5 +1,6 @@
+use chrono::{DateTime, Utc};
 use serde::{Deserialize, Serialize};
-use sqlx::{Error, PgPool, types::Uuid};
+use sqlx::{Error, PgConnection, PgPool, types::Uuid};
 
 #[derive(Debug, Deserialize, Serialize, Clone, sqlx::FromRow)]
 pub struct Product {
@@ -7,48 +8,410 @@ pub struct Product {
     pub price: i32,
 }
 
+#[derive(Debug, Clone, Serialize)]
+pub struct ImportedProduct {
+    pub id: i32,
+    pub price: i32,
+    pub vendor_code: Option<String>,
+    pub min_price: Option<i32>,
+    pub max_price: Option<i32>,
+}
+
+#[derive(Debug, Serialize, Clone, sqlx::FromRow)]
+pub struct GoodSettings {
+    pub id: i32,
+    pub price: i32,
+    pub active: bool,
+    pub vendor_code: Option<String>,
+    pub min_price: Option<i32>,
+    pub max_price: Option<i32>,
+}
+
+#[derive(Debug, Clone, sqlx::FromRow)]
+pub struct PriceBounds {
+    pub id: i32,
+    pub min_price: Option<i32>,
+    pub max_price: Option<i32>,
+}
+
+#[derive(Debug, Clone)]
+pub struct UpsertedProduct {
+    pub id: i32,
+    pub inserted: bool,
+}
+
+#[derive(Debug, Serialize, Clone, sqlx::FromRow)]
+pub struct GoodStatus {
+    pub id: i32,
+    pub price: i32,
+    pub active: bool,
+    pub observed_basic: Option<i32>,
+    pub observed_total: Option<i32>,
+    pub observed_at: Option<DateTime<Utc>>,
+    pub uploaded_price: Option<i32>,
+    pub uploaded_at: Option<DateTime<Utc>>,
+    pub error: Option<String>,
+    pub vendor_code: Option<String>,
+    pub min_price: Option<i32>,
+    pub max_price: Option<i32>,
+}
+
+#[derive(Debug, Deserialize, Clone)]
+pub struct GoodsFilter {
+    pub active: Option<bool>,
+    pub failed: Option<bool>,
+    #[serde(default)]
+    pub sort: GoodsSort,
+    #[serde(default)]
+    pub order: SortOrder,
+}
+
+#[derive(Debug, Deserialize, Default, Clone, Copy)]
+#[serde(rename_all = "snake_case")]
+pub enum GoodsSort {
+    #[default]
+    Id,
+    Price,
+    ObservedAt,
+    UploadedAt,
+}
+
+impl GoodsSort {
+    fn as_str(&self) -> &'static str {
+        match self {
+            GoodsSort::Id => "id",
+            GoodsSort::Price => "price",
+            GoodsSort::ObservedAt => "observed_at",
+            GoodsSort::UploadedAt => "uploaded_at",
+        }
+    }
+}
+
+#[derive(Debug, Deserialize, Default, Clone, Copy, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum SortOrder {
+    #[default]
+    Asc,
+    Desc,
+}
+
+pub const MISSING_ON_WB: &str = "Not found on WB";
+
+/// Outcome of an upsert that has to keep the supplier within its plan's product limit.
+#[derive(Debug)]
+pub enum Limited<T> {
+    Within(T),
+    /// nothing was written, the supplier would have had this many products
+    Exceeded(i64),
+}
+
 impl Product {
     pub fn new(id: i32, price: i32) -> Self {
         Self { id, price }
     }
 
-    pub async fn create_many(client: &PgPool, api_key: &Uuid, products: &[Product]) -> Result<(), Error> {
+    pub async fn create_many(
+        client: &PgPool,
+        api_key: &Uuid,
+        products: &[Product],
+        max_products: i32,
+    ) -> Result<Limited<Vec<UpsertedProduct>>, Error> {
+        let ids: Vec<i32> = products.iter().map(|p| p.id).collect();
+        let prices: Vec<i32> = products.iter().map(|p| p.price).collect();
+
         let mut transaction = client.begin().await?;
+        if let Some(total) = exceeds_limit(&mut transaction, api_key, &ids, max_products).await? {
+            return Ok(Limited::Exceeded(total));
+        }
 
-        for product in products {
-            sqlx::query!(
+        // xmax is zero only for rows this statement inserted, conflicting rows get updated
+        let upserted = sqlx::query_as!(
+            UpsertedProduct,
             r#"
             INSERT INTO products (id, price, supplier_api_key)
-            VALUES ($1, $2, $3)
-            ON CONFLICT (id) DO UPDATE
-            SET price = $2
+            SELECT id, price, $3 FROM UNNEST($1::INTEGER[], $2::INTEGER[]) AS input(id, price)
+            ON CONFLICT (supplier_api_key, id) DO UPDATE
+            SET price = EXCLUDED.price
+            RETURNING id, (xmax = 0) AS "inserted!"
             "#,
-            product.id,
-            product.price,
+            &ids,
+            &prices,
             api_key
         )
-                .execute(&mut *transaction)
-                .await?;
+            .fetch_all(&mut *transaction)
+            .await?;
+        transaction.commit().await?;
+
+        Ok(Limited::Within(upserted))
+    }
+
+    pub async fn import_many(
+        client: &PgPool,
+        api_key: &Uuid,
+        products: &[ImportedProduct],
+        max_products: i32,
+    ) -> Result<Limited<Vec<UpsertedProduct>>, Error> {
+        let ids: Vec<i32> = products.iter().map(|p| p.id).collect();
+        let prices: Vec<i32> = products.iter().map(|p| p.price).collect();
+        let vendor_codes: Vec<Option<String>> = products.iter().map(|p| p.vendor_code.clone()).collect();
+        let min_prices: Vec<Option<i32>> = products.iter().map(|p| p.min_price).collect();
+        let max_prices: Vec<Option<i32>> = products.iter().map(|p| p.max_price).collect();
+
+        let mut transaction = client.begin().await?;
+        if let Some(total) = exceeds_limit(&mut transaction, api_key, &ids, max_products).await? {
+            return Ok(Limited::Exceeded(total));
         }
 
+        let upserted = sqlx::query_as!(
+            UpsertedProduct,
+            r#"
+            INSERT INTO products (id, price, vendor_code, min_price, max_price, supplier_api_key)
+            SELECT id, price, vendor_code, min_price, max_price, $6
+            FROM UNNEST($1::INTEGER[], $2::INTEGER[], $3::TEXT[], $4::INTEGER[], $5::INTEGER[])
+                AS input(id, price, vendor_code, min_price, max_price)
+            ON CONFLICT (supplier_api_key, id) DO UPDATE
+            SET price = EXCLUDED.price,
+                vendor_code = COALESCE(EXCLUDED.vendor_code, products.vendor_code),
+                min_price = COALESCE(EXCLUDED.min_price, products.min_price),
+                max_price = COALESCE(EXCLUDED.max_price, products.max_price)
+            RETURNING id, (xmax = 0) AS "inserted!"
+            "#,
+            &ids,
+            &prices,
+            &vendor_codes as &[Option<String>],
+            &min_prices as &[Option<i32>],
+            &max_prices as &[Option<i32>],
+            api_key
+        )
+            .fetch_all(&mut *transaction)
+            .await?;
         transaction.commit().await?;
-        Ok(())
+
+        Ok(Limited::Within(upserted))
     }
 
+    pub async fn find_by_vendor_codes(client: &PgPool, api_key: &Uuid, vendor_codes: &[String]) -> Result<Vec<(String, i32)>, Error> {
+        sqlx::query!(
+            r#"
+            SELECT vendor_code AS "vendor_code!", id FROM products
+            WHERE supplier_api_key = $1 AND vendor_code = ANY($2)
+            "#,
+            api_key,
+            vendor_codes
+        )
+            .fetch_all(client)
+            .await
+            .map(|records| records.into_iter().map(|record| (record.vendor_code, record.id)).collect())
+    }
+
+    pub async fn get_bounds_by_apikey(client: &PgPool, api_key: &Uuid) -> Result<Vec<PriceBounds>, Error> {
+        sqlx::query_as!(
+            PriceBounds,
+            r#"
+            SELECT id, min_price, max_price FROM products
+            WHERE supplier_api_key = $1 AND (min_price IS NOT NULL OR max_price IS NOT NULL)
+            "#,
+            api_key
+        )
+            .fetch_all(client)
+            .await
+    }
 
-    pub async fn get_by_apikey(client: &PgPool, api_key: &Uuid) -> Result<Vec<Product>, Error> {
+    pub async fn get_by_apikey(client: &PgPool, api_key: &Uuid, active: Option<bool>) -> Result<Vec<Product>, Error> {
         sqlx::query_as!(
             Product,
             r#"
             SELECT id, price FROM products
-            WHERE supplier_api_key = $1
+            WHERE supplier_api_key = $1 AND ($2::BOOLEAN IS NULL OR active = $2)
             "#,
-            api_key
+            api_key,
+            active
+        )
+            .fetch_all(client)
+            .await
+    }
+
+    pub async fn list_status_by_apikey(
+        client: &PgPool,
+        api_key: &Uuid,
+        filter: &GoodsFilter,
+        limit: i64,
+        offset: i64,
+    ) -> Result<Vec<GoodStatus>, Error> {
+        sqlx::query_as!(
+            GoodStatus,
+            r#"
+            SELECT p.id, p.price, p.active,
+                   observed.basic AS observed_basic, observed.total AS observed_total,
+                   observed.recorded_at AS "observed_at?",
+                   uploaded.price AS uploaded_price, uploaded.recorded_at AS "uploaded_at?",
+                   p.last_error AS error, p.vendor_code, p.min_price, p.max_price
+            FROM products p
+            LEFT JOIN LATERAL (
+                SELECT basic, total, recorded_at FROM price_history h
+                WHERE h.supplier_api_key = p.supplier_api_key AND h.product_id = p.id AND h.kind = 'observed'
+                ORDER BY recorded_at DESC
+                LIMIT 1
+            ) observed ON TRUE
+            LEFT JOIN LATERAL (
+                SELECT price, recorded_at FROM price_history h
+                WHERE h.supplier_api_key = p.supplier_api_key AND h.product_id = p.id AND h.kind = 'uploaded'
+                ORDER BY recorded_at DESC
+                LIMIT 1
+            ) uploaded ON TRUE
+            WHERE p.supplier_api_key = $1
+              AND ($2::BOOLEAN IS NULL OR p.active = $2)
+              AND ($3::BOOLEAN IS NULL OR (p.last_error IS NOT NULL) = $3)
+            ORDER BY
+                CASE WHEN NOT $5 AND $4 = 'price' THEN p.price END ASC,
+                CASE WHEN $5 AND $4 = 'price' THEN p.price END DESC,
+                CASE WHEN NOT $5 AND $4 = 'observed_at' THEN observed.recorded_at END ASC NULLS FIRST,
+                CASE WHEN $5 AND $4 = 'observed_at' THEN observed.recorded_at END DESC NULLS LAST,
+                CASE WHEN NOT $5 AND $4 = 'uploaded_at' THEN uploaded.recorded_at END ASC NULLS FIRST,
+                CASE WHEN $5 AND $4 = 'uploaded_at' THEN uploaded.recorded_at END DESC NULLS LAST,
+                CASE WHEN NOT $5 THEN p.id END ASC,
+                CASE WHEN $5 THEN p.id END DESC
+            LIMIT $6 OFFSET $7
+            "#,
+            api_key,
+            filter.active,
+            filter.failed,
+            filter.sort.as_str(),
+            filter.order == SortOrder::Desc,
+            limit,
+            offset
         )
             .fetch_all(client)
             .await
     }
 
+    pub async fn set_errors(client: &PgPool, api_key: &Uuid, missing: &[i32], observed: &[i32]) -> Result<(), Error> {
+        sqlx::query!(
+            r#"
+            UPDATE products
+            SET last_error = CASE WHEN id = ANY($2) THEN $4 END
+            WHERE supplier_api_key = $1 AND (id = ANY($2) OR id = ANY($3))
+            "#,
+            api_key,
+            missing,
+            observed,
+            MISSING_ON_WB
+        )
+            .execute(client)
+            .await?;
+
+        Ok(())
+    }
+
+    pub async fn get(client: &PgPool, api_key: &Uuid, id: i32) -> Result<Option<Product>, Error> {
+        sqlx::query_as!(
+            Product,
+            r#"
+            SELECT id, price FROM products
+            WHERE supplier_api_key = $1 AND id = $2
+            "#,
+            api_key,
+            id
+        )
+            .fetch_optional(client)
+            .await
+    }
+
+    pub async fn get_settings(client: &PgPool, api_key: &Uuid, id: i32) -> Result<Option<GoodSettings>, Error> {
+        sqlx::query_as!(
+            GoodSettings,
+            r#"
+            SELECT id, price, active, vendor_code, min_price, max_price FROM products
+            WHERE supplier_api_key = $1 AND id = $2
+            "#,
+            api_key,
+            id
+        )
+            .fetch_optional(client)
+            .await
+    }
+
+    /// Upserts the good and returns its settings from before, read under the same lock.
+    pub async fn save_settings(
+        client: &PgPool,
+        api_key: &Uuid,
+        good: &GoodSettings,
+        max_products: i32,
+    ) -> Result<Limited<Option<GoodSettings>>, Error> {
+        let mut transaction = client.begin().await?;
+        if let Some(total) = exceeds_limit(&mut transaction, api_key, &[good.id], max_products).await? {
+            return Ok(Limited::Exceeded(total));
+        }
+
+        let old = sqlx::query_as!(
+            GoodSettings,
+            r#"
+            SELECT id, price, active, vendor_code, min_price, max_price FROM products
+            WHERE supplier_api_key = $1 AND id = $2
+            "#,
+            api_key,
+            good.id
+        )
+            .fetch_optional(&mut *transaction)
+            .await?;
+        sqlx::query!(
+            r#"
+            INSERT INTO products (id, price, active, vendor_code, min_price, max_price, supplier_api_key)
+            VALUES ($1, $2, $3, $4, $5, $6, $7)
+            ON CONFLICT (supplier_api_key, id) DO UPDATE
+            SET price = EXCLUDED.price,
+                active = EXCLUDED.active,
+                vendor_code = EXCLUDED.vendor_code,
+                min_price = EXCLUDED.min_price,
+                max_price = EXCLUDED.max_price
+            "#,
+            good.id,
+            good.price,
+            good.active,
+            good.vendor_code,
+            good.min_price,
+            good.max_price,
+            api_key
+        )
+            .execute(&mut *transaction)
+            .await?;
+        transaction.commit().await?;
+
+        Ok(Limited::Within(old))
+    }
+
+    /// Writes the good only if it still has the `old` settings, `RowNotFound` when it changed or is gone.
+    pub async fn update_settings(client: &PgPool, api_key: &Uuid, old: &GoodSettings, good: &GoodSettings) -> Result<(), Error> {
+        if sqlx::query!(
+            r#"
+            UPDATE products
+            SET price = $3, active = $4, vendor_code = $5, min_price = $6, max_price = $7
+            WHERE supplier_api_key = $1 AND id = $2
+              AND (price, active, vendor_code, min_price, max_price)
+                  IS NOT DISTINCT FROM ($8, $9, $10, $11, $12)
+            "#,
+            api_key,
+            good.id,
+            good.price,
+            good.active,
+            good.vendor_code,
+            good.min_price,
+            good.max_price,
+            old.price,
+            old.active,
+            old.vendor_code,
+            old.min_price,
+            old.max_price
+        )
+            .execute(client)
+            .await?
+            .rows_affected() == 0 {
+            return Err(Error::RowNotFound)
+        }
+
+        Ok(())
+    }
+
     pub async fn count_by_apikey(client: &PgPool, api_key: &Uuid) -> Result<i64, Error> {
         match sqlx::query!(
             r#"
@@ -66,11 +429,26 @@ impl Product {
         }
     }
 
+    pub async fn set_active(client: &PgPool, api_key: &Uuid, ids: Option<&[i32]>, active: bool) -> Result<u64, Error> {
+        sqlx::query!(
+            r#"
+            UPDATE products SET active = $3
+            WHERE supplier_api_key = $1 AND ($2::INTEGER[] IS NULL OR id = ANY($2))
+            "#,
+            api_key,
+            ids,
+            active
+        )
+            .execute(client)
+            .await
+            .map(|result| result.rows_affected())
+    }
+
     pub async fn delete_by_id_and_api_key(client: &PgPool, id: i32, api_key: &Uuid) -> Result<(), Error> {
         if sqlx::query!(
             r#"
             DELETE FROM products
-            WHERE id = $1 AND supplier_api_key = $2
+            WHERE supplier_api_key = $2 AND id = $1
             "#,
             id,
             api_key
@@ -84,3 +462,38 @@ impl Product {
         Ok(())
     }
 }
+
+// locking the supplier row serializes upserts of one supplier, so each counts the goods the others added;
+// NO KEY keeps it from blocking inserts elsewhere that only reference the supplier
+async fn exceeds_limit(
+    transaction: &mut PgConnection,
+    api_key: &Uuid,
+    ids: &[i32],
+    max_products: i32,
+) -> Result<Option<i64>, Error> {
+    sqlx::query!(
+        r#"
+        SELECT api_key FROM suppliers WHERE api_key = $1 FOR NO KEY UPDATE
+        "#,
+        api_key
+    )
+        .fetch_one(&mut *transaction)
+        .await?;
+
+    let total = sqlx::query!(
+        r#"
+        SELECT COUNT(*) AS "count!" FROM (
+            SELECT id FROM products WHERE supplier_api_key = $1
+            UNION
+            SELECT UNNEST($2::INTEGER[])
+        ) AS ids
+        "#,
+        api_key,
+        ids
+    )
+        .fetch_one(&mut *transaction)
+        .await?
+        .count;
+
+    Ok((total > max_products as i64).then_some(total))
+}
@@ -0,0 +1,110 @@
+use chrono::{DateTime, Utc};
+use serde::Serialize;
+use sqlx::{Error, PgPool, types::Uuid};
+
+#[derive(Debug, Serialize, Clone, sqlx::FromRow)]
+pub struct Run {
+    pub id: i64,
+    pub parent_id: Option<i64>,
+    pub supplier_api_key: Option<Uuid>,
+    pub started_at: DateTime<Utc>,
+    pub finished_at: Option<DateTime<Utc>>,
+    pub products_checked: i32,
+    pub products_changed: i32,
+    pub error_class: Option<String>,
+    pub error_message: Option<String>,
+    pub dry_run: bool,
+    pub frozen: bool,
+}
+
+impl Run {
+    pub async fn start(
+        client: &PgPool,
+        parent_id: Option<i64>,
+        api_key: Option<&Uuid>,
+        dry_run: bool,
+        frozen: bool,
+    ) -> Result<i64, Error> {
+        sqlx::query!(
+            r#"
+            INSERT INTO price_update_runs (parent_id, supplier_api_key, dry_run, frozen)
+            VALUES ($1, $2, $3, $4)
+            RETURNING id
+            "#,
+            parent_id,
+            api_key,
+            dry_run,
+            frozen
+        )
+            .fetch_one(client)
+            .await
+            .map(|record| record.id)
+    }
+
+    pub async fn finish(
+        client: &PgPool,
+        id: i64,
+        products_checked: i32,
+        products_changed: i32,
+        error_class: Option<&str>,
+        error_message: Option<&str>,
+    ) -> Result<(), Error> {
+        sqlx::query!(
+            r#"
+            UPDATE price_update_runs
+            SET finished_at = NOW(),
+                products_checked = $2,
+                products_changed = $3,
+                error_class = $4,
+                error_message = $5
+            WHERE id = $1
+            "#,
+            id,
+            products_checked,
+            products_changed,
+            error_class,
+            error_message
+        )
+            .execute(client)
+            .await?;
+
+        Ok(())
+    }
+
+    /// Start of the last scheduled run that succeeded, failed runs and manual syncs don't count
+    /// towards the plan's update interval.
+    pub async fn last_started_at(client: &PgPool, api_key: &Uuid) -> Result<Option<DateTime<Utc>>, Error> {
+        sqlx::query!(
+            r#"
+            SELECT MAX(started_at) AS started_at FROM price_update_runs
+            WHERE supplier_api_key = $1
+              AND parent_id IS NOT NULL
+              AND finished_at IS NOT NULL
+              AND error_class IS NULL
+            "#,
+            api_key
+        )
+            .fetch_one(client)
+            .await
+            .map(|record| record.started_at)
+    }
+
+    pub async fn list_by_apikey(client: &PgPool, api_key: &Uuid, limit: i64, offset: i64) -> Result<Vec<Run>, Error> {
+        sqlx::query_as!(
+            Run,
+            r#"
+            SELECT id, parent_id, supplier_api_key, started_at, finished_at,
+                   products_checked, products_changed, error_class, error_message, dry_run, frozen
+            FROM price_update_runs
+            WHERE supplier_api_key = $1
+            ORDER BY started_at DESC
+            LIMIT $2 OFFSET $3
+            "#,
+            api_key,
+            limit,
+            offset
+        )
+            .fetch_all(client)
+            .await
+    }
+}
@@ -0,0 +1,115 @@
+use chrono::{NaiveDate, NaiveTime};
+use serde::{Deserialize, Serialize};
+use sqlx::{Error, PgPool, types::Uuid};
+
+#[derive(Debug, Deserialize, Serialize, Clone, sqlx::FromRow)]
+pub struct PriceWindow {
+    #[serde(skip)]
+    pub product_id: i32,
+    pub weekdays: Option<Vec<i16>>,
+    pub start_date: Option<NaiveDate>,
+    pub end_date: Option<NaiveDate>,
+    pub start_time: NaiveTime,
+    pub end_time: NaiveTime,
+    pub price: i32,
+}
+
+impl PriceWindow {
+    pub async fn get_by_apikey(client: &PgPool, api_key: &Uuid) -> Result<Vec<PriceWindow>, Error> {
+        sqlx::query_as!(
+            PriceWindow,
+            r#"
+            SELECT product_id, weekdays, start_date, end_date, start_time, end_time, price
+            FROM price_schedules
+            WHERE supplier_api_key = $1
+            ORDER BY product_id, position
+            "#,
+            api_key
+        )
+            .fetch_all(client)
+            .await
+    }
+
+    pub async fn get_by_product(client: &PgPool, api_key: &Uuid, product_id: i32) -> Result<Vec<PriceWindow>, Error> {
+        sqlx::query_as!(
+            PriceWindow,
+            r#"
+            SELECT product_id, weekdays, start_date, end_date, start_time, end_time, price
+            FROM price_schedules
+            WHERE supplier_api_key = $1 AND product_id = $2
+            ORDER BY position
+            "#,
+            api_key,
+            product_id
+        )
+            .fetch_all(client)
+            .await
+    }
+
+    pub async fn replace_for_product(
+        client: &PgPool,
+        api_key: &Uuid,
+        product_id: i32,
+        windows: &[PriceWindow],
+    ) -> Result<(), Error> {
+        let mut transaction = client.begin().await?;
+
+        sqlx::query!(
+            r#"
+            SELECT id FROM products
+            WHERE id = $1 AND supplier_api_key = $2
+            FOR UPDATE
+            "#,
+            product_id,
+            api_key
+        )
+            .fetch_optional(&mut *transaction)
+            .await?
+            .ok_or(Error::RowNotFound)?;
+
+        sqlx::query!(
+            r#"
+            DELETE FROM price_schedules
+            WHERE supplier_api_key = $1 AND product_id = $2
+            "#,
+            api_key,
+            product_id
+        )
+            .execute(&mut *transaction)
+            .await?;
+
+        // postgres arrays can't nest, so each window's weekdays travel as an array literal like "{1,5}"
+        let weekdays: Vec<Option<String>> = windows
+            .iter()
+            .map(|w| w.weekdays.as_ref().map(|days| format!("{{{}}}", days.iter().map(i16::to_string).collect::<Vec<_>>().join(","))))
+            .collect();
+        let start_dates: Vec<Option<NaiveDate>> = windows.iter().map(|w| w.start_date).collect();
+        let end_dates: Vec<Option<NaiveDate>> = windows.iter().map(|w| w.end_date).collect();
+        let start_times: Vec<NaiveTime> = windows.iter().map(|w| w.start_time).collect();
+        let end_times: Vec<NaiveTime> = windows.iter().map(|w| w.end_time).collect();
+        let prices: Vec<i32> = windows.iter().map(|w| w.price).collect();
+
+        sqlx::query!(
+            r#"
+            INSERT INTO price_schedules
+                (product_id, supplier_api_key, position, weekdays, start_date, end_date, start_time, end_time, price)
+            SELECT $1, $2, position - 1, weekdays::SMALLINT[], start_date, end_date, start_time, end_time, price
+            FROM UNNEST($3::TEXT[], $4::DATE[], $5::DATE[], $6::TIME[], $7::TIME[], $8::INTEGER[])
+                WITH ORDINALITY AS input(weekdays, start_date, end_date, start_time, end_time, price, position)
+            "#,
+            product_id,
+            api_key,
+            &weekdays as &[Option<String>],
+            &start_dates as &[Option<NaiveDate>],
+            &end_dates as &[Option<NaiveDate>],
+            &start_times,
+            &end_times,
+            &prices
+        )
+            .execute(&mut *transaction)
+            .await?;
+
+        transaction.commit().await?;
+        Ok(())
+    }
+}
@@ -1,11 +1,23 @@
-use std::fmt::{Display, Formatter};
+use std::fmt::{Debug, Display, Formatter};
+use serde::Deserialize;
 use sqlx::{Error, PgPool, types::Uuid};
+use tracing::warn;
+use crate::crypto::{EncryptedToken, TokenCipher};
+use crate::db::api_key::{ApiKey, IssuedApiKey};
 
-#[derive(Debug, Clone, sqlx::FromRow)]
+#[derive(Clone)]
 pub struct Supplier {
+    // the supplier id, the keys used to authenticate live in api_keys
     pub api_key: Uuid,
     pub wb_id: Option<i32>,
     pub wb_jwt: Option<String>,
+    pub auth_failures: i32,
+    pub needs_reauth: bool,
+    pub dry_run: bool,
+    pub active: bool,
+    pub timezone: String,
+    pub plan: String,
+    pub disabled: bool,
 }
 
 impl Display for Supplier {
@@ -19,12 +31,83 @@ impl Display for Supplier {
     }
 }
 
+impl Debug for Supplier {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("Supplier")
+            .field("api_key", &self.api_key)
+            .field("wb_id", &self.wb_id)
+            .field("wb_jwt", &self.wb_jwt.as_ref().map(|_| "<redacted>"))
+            .field("auth_failures", &self.auth_failures)
+            .field("needs_reauth", &self.needs_reauth)
+            .field("dry_run", &self.dry_run)
+            .field("active", &self.active)
+            .field("timezone", &self.timezone)
+            .field("plan", &self.plan)
+            .field("disabled", &self.disabled)
+            .finish()
+    }
+}
+
+#[derive(Debug, Default, Deserialize)]
+pub struct SupplierFilter {
+    // supplier id prefix or exact wb id
+    pub search: Option<String>,
+    pub plan: Option<String>,
+    pub disabled: Option<bool>,
+}
+
+#[derive(sqlx::FromRow)]
+struct SupplierRow {
+    api_key: Uuid,
+    wb_id: Option<i32>,
+    wb_jwt_ciphertext: Option<Vec<u8>>,
+    wb_jwt_dek: Option<Vec<u8>>,
+    wb_jwt_key_id: Option<String>,
+    auth_failures: i32,
+    needs_reauth: bool,
+    dry_run: bool,
+    active: bool,
+    timezone: String,
+    plan: String,
+    disabled: bool,
+}
+
+impl SupplierRow {
+    fn decrypt(self, cipher: &TokenCipher) -> Result<Supplier, Error> {
+        let wb_jwt = match (self.wb_jwt_ciphertext, self.wb_jwt_dek, self.wb_jwt_key_id) {
+            (Some(ciphertext), Some(dek), Some(key_id)) => Some(
+                cipher
+                    .decrypt(&EncryptedToken { ciphertext, dek, key_id })
+                    .map_err(|err| Error::Decode(format!("wb jwt of supplier {}: {}", self.api_key, err).into()))?
+            ),
+            _ => None,
+        };
+
+        Ok(Supplier {
+            api_key: self.api_key,
+            wb_id: self.wb_id,
+            wb_jwt,
+            auth_failures: self.auth_failures,
+            needs_reauth: self.needs_reauth,
+            dry_run: self.dry_run,
+            active: self.active,
+            timezone: self.timezone,
+            plan: self.plan,
+            disabled: self.disabled,
+        })
+    }
+}
+
 impl Supplier {
-    pub async fn list(pool: &PgPool, limit: i64, offset: i64) -> Result<Vec<Supplier>, Error> {
+    /// Each supplier is decrypted on its own, so one unreadable token doesn't hide the others.
+    pub async fn list(pool: &PgPool, cipher: &TokenCipher, limit: i64, offset: i64) -> Result<Vec<Result<Supplier, Error>>, Error> {
         sqlx::query_as!(
-            Supplier,
+            SupplierRow,
             r#"
-            SELECT api_key, wb_id, wb_jwt FROM suppliers
+            SELECT api_key, wb_id, wb_jwt_ciphertext, wb_jwt_dek, wb_jwt_key_id,
+                   auth_failures, needs_reauth, dry_run, active, timezone, plan,
+                   disabled
+            FROM suppliers
             ORDER BY api_key
             LIMIT $1 OFFSET $2
             "#,
@@ -33,38 +116,98 @@ impl Supplier {
         )
             .fetch_all(pool)
             .await
+            .map(|rows| rows.into_iter().map(|row| row.decrypt(cipher)).collect())
+    }
+
+    pub async fn search(
+        pool: &PgPool,
+        cipher: &TokenCipher,
+        filter: &SupplierFilter,
+        limit: i64,
+        offset: i64,
+    ) -> Result<Vec<Supplier>, Error> {
+        sqlx::query_as!(
+            SupplierRow,
+            r#"
+            SELECT api_key, wb_id, wb_jwt_ciphertext, wb_jwt_dek, wb_jwt_key_id,
+                   auth_failures, needs_reauth, dry_run, active, timezone, plan,
+                   disabled
+            FROM suppliers
+            WHERE ($1::TEXT IS NULL OR starts_with(api_key::TEXT, lower($1)) OR wb_id::TEXT = $1)
+              AND ($2::TEXT IS NULL OR plan = $2)
+              AND ($3::BOOLEAN IS NULL OR disabled = $3)
+            ORDER BY api_key
+            LIMIT $4 OFFSET $5
+            "#,
+            filter.search,
+            filter.plan,
+            filter.disabled,
+            limit,
+            offset
+        )
+            .fetch_all(pool)
+            .await?
+            .into_iter()
+            .map(|row| row.decrypt(cipher))
+            .collect()
     }
 
-    pub async fn get(client: &PgPool, api_key: &Uuid) -> Result<Option<Supplier>, Error> {
+    pub async fn get(client: &PgPool, cipher: &TokenCipher, api_key: &Uuid) -> Result<Option<Supplier>, Error> {
         sqlx::query_as!(
-            Supplier,
+            SupplierRow,
             r#"
-            SELECT api_key, wb_id, wb_jwt FROM suppliers WHERE api_key = $1
+            SELECT api_key, wb_id, wb_jwt_ciphertext, wb_jwt_dek, wb_jwt_key_id,
+                   auth_failures, needs_reauth, dry_run, active, timezone, plan,
+                   disabled
+            FROM suppliers
+            WHERE api_key = $1
             "#,
             api_key
         )
             .fetch_optional(client)
-            .await
+            .await?
+            .map(|row| row.decrypt(cipher))
+            .transpose()
     }
 
-    pub async fn create(client: &PgPool) -> Result<Supplier, Error> {
-        sqlx::query_as!(
-            Supplier,
+    /// Creates the supplier together with its first key, so a supplier never exists without one.
+    pub async fn create(client: &PgPool, cipher: &TokenCipher, label: &str) -> Result<(Supplier, IssuedApiKey), Error> {
+        let mut transaction = client.begin().await?;
+
+        let supplier = sqlx::query_as!(
+            SupplierRow,
             r#"
-            INSERT INTO suppliers DEFAULT VALUES 
-            RETURNING *
-            "#, 
+            INSERT INTO suppliers DEFAULT VALUES
+            RETURNING api_key, wb_id, wb_jwt_ciphertext, wb_jwt_dek, wb_jwt_key_id,
+                      auth_failures, needs_reauth, dry_run, active, timezone, plan,
+                      disabled
+            "#,
         )
-            .fetch_one(client)
-            .await
+            .fetch_one(&mut *transaction)
+            .await?
+            .decrypt(cipher)?;
+        let issued = ApiKey::create(&mut *transaction, &supplier.api_key, label, None).await?;
+
+        transaction.commit().await?;
+        Ok((supplier, issued))
     }
 
-    pub async fn set_wb_jwt(client: &PgPool, api_key: &Uuid, jwt: &str) -> Result<(), Error> {
+    pub async fn set_wb_jwt(client: &PgPool, cipher: &TokenCipher, api_key: &Uuid, jwt: &str) -> Result<(), Error> {
+        let token = cipher.encrypt(jwt).map_err(|err| Error::Encode(Box::new(err)))?;
+        // a new token gets a fresh start after WB rejected the old one
         sqlx::query!(
             r#"
-            UPDATE suppliers SET wb_jwt = $1 WHERE api_key = $2
+            UPDATE suppliers
+            SET wb_jwt_ciphertext = $1,
+                wb_jwt_dek = $2,
+                wb_jwt_key_id = $3,
+                auth_failures = 0,
+                needs_reauth = FALSE
+            WHERE api_key = $4
             "#,
-            jwt,
+            token.ciphertext,
+            token.dek,
+            token.key_id,
             api_key
         )
             .execute(client)
@@ -86,4 +229,214 @@ impl Supplier {
 
         Ok(())
     }
+
+    // runtime queries on purpose: the plaintext column is gone from the schema the macros are checked against
+    pub async fn encrypt_plain_wb_jwts(client: &PgPool, cipher: &TokenCipher) -> Result<u64, Error> {
+        let has_plain = sqlx::query_scalar::<_, bool>(
+            r#"
+            SELECT EXISTS (
+                SELECT 1 FROM information_schema.columns
+                WHERE table_schema = current_schema() AND table_name = 'suppliers' AND column_name = 'wb_jwt'
+            )
+            "#
+        )
+            .fetch_one(client)
+            .await?;
+        if !has_plain {
+            return Ok(0);
+        }
+
+        let plain = sqlx::query_as::<_, (Uuid, String)>(
+            r#"
+            SELECT api_key, wb_jwt FROM suppliers
+            WHERE wb_jwt IS NOT NULL
+            "#
+        )
+            .fetch_all(client)
+            .await?;
+        let mut encrypted = 0;
+        for (api_key, jwt) in plain {
+            let token = cipher.encrypt(&jwt).map_err(|err| Error::Encode(Box::new(err)))?;
+            sqlx::query(
+                r#"
+                UPDATE suppliers
+                SET wb_jwt = NULL, wb_jwt_ciphertext = $1, wb_jwt_dek = $2, wb_jwt_key_id = $3
+                WHERE api_key = $4
+                "#
+            )
+                .bind(token.ciphertext)
+                .bind(token.dek)
+                .bind(token.key_id)
+                .bind(api_key)
+                .execute(client)
+                .await?;
+            encrypted += 1;
+        }
+
+        Ok(encrypted)
+    }
+
+    /// Moves tokens to the active key. A token whose key is gone stays as it is, its supplier is
+    /// skipped by the updater until a new wb jwt is set.
+    pub async fn rewrap_wb_jwts(client: &PgPool, cipher: &TokenCipher) -> Result<u64, Error> {
+        let mut rewrapped = 0;
+
+        let stale = sqlx::query!(
+            r#"
+            SELECT api_key, wb_jwt_ciphertext AS "ciphertext!", wb_jwt_dek AS "dek!", wb_jwt_key_id AS "key_id!"
+            FROM suppliers
+            WHERE wb_jwt_key_id IS NOT NULL AND wb_jwt_key_id <> $1
+            "#,
+            cipher.active_key_id()
+        )
+            .fetch_all(client)
+            .await?;
+        for record in stale {
+            let token = EncryptedToken { ciphertext: record.ciphertext, dek: record.dek, key_id: record.key_id };
+            let token = match cipher.rewrap(&token) {
+                Ok(token) => token,
+                Err(err) => {
+                    warn!("Failed rewrap wb jwt of supplier {}: {}", record.api_key, err);
+                    continue;
+                }
+            };
+            sqlx::query!(
+                r#"
+                UPDATE suppliers SET wb_jwt_dek = $1, wb_jwt_key_id = $2
+                WHERE api_key = $3
+                "#,
+                token.dek,
+                token.key_id,
+                record.api_key
+            )
+                .execute(client)
+                .await?;
+            rewrapped += 1;
+        }
+
+        Ok(rewrapped)
+    }
+
+    pub async fn set_dry_run(client: &PgPool, api_key: &Uuid, dry_run: bool) -> Result<(), Error> {
+        sqlx::query!(
+            r#"
+            UPDATE suppliers SET dry_run = $1 WHERE api_key = $2
+            "#,
+            dry_run,
+            api_key
+        )
+            .execute(client)
+            .await?;
+
+        Ok(())
+    }
+
+    pub async fn set_active(client: &PgPool, api_key: &Uuid, active: bool) -> Result<(), Error> {
+        sqlx::query!(
+            r#"
+            UPDATE suppliers SET active = $1 WHERE api_key = $2
+            "#,
+            active,
+            api_key
+        )
+            .execute(client)
+            .await?;
+
+        Ok(())
+    }
+
+    pub async fn set_timezone(client: &PgPool, api_key: &Uuid, timezone: &str) -> Result<(), Error> {
+        sqlx::query!(
+            r#"
+            UPDATE suppliers SET timezone = $1 WHERE api_key = $2
+            "#,
+            timezone,
+            api_key
+        )
+            .execute(client)
+            .await?;
+
+        Ok(())
+    }
+
+    pub async fn set_plan(client: &PgPool, api_key: &Uuid, plan: &str) -> Result<(), Error> {
+        if sqlx::query!(
+            r#"
+            UPDATE suppliers SET plan = $1 WHERE api_key = $2
+            "#,
+            plan,
+            api_key
+        )
+            .execute(client)
+            .await?
+            .rows_affected() == 0 {
+            return Err(Error::RowNotFound)
+        }
+
+        Ok(())
+    }
+
+    pub async fn set_disabled(client: &PgPool, api_key: &Uuid, disabled: bool) -> Result<(), Error> {
+        if sqlx::query!(
+            r#"
+            UPDATE suppliers SET disabled = $1 WHERE api_key = $2
+            "#,
+            disabled,
+            api_key
+        )
+            .execute(client)
+            .await?
+            .rows_affected() == 0 {
+            return Err(Error::RowNotFound)
+        }
+
+        Ok(())
+    }
+
+    pub async fn delete(client: &PgPool, api_key: &Uuid) -> Result<(), Error> {
+        if sqlx::query!(
+            r#"
+            DELETE FROM suppliers WHERE api_key = $1
+            "#,
+            api_key
+        )
+            .execute(client)
+            .await?
+            .rows_affected() == 0 {
+            return Err(Error::RowNotFound)
+        }
+
+        Ok(())
+    }
+
+    pub async fn register_auth_failure(client: &PgPool, api_key: &Uuid, limit: i32) -> Result<bool, Error> {
+        sqlx::query!(
+            r#"
+            UPDATE suppliers
+            SET auth_failures = auth_failures + 1,
+                needs_reauth = auth_failures + 1 >= $2
+            WHERE api_key = $1
+            RETURNING needs_reauth
+            "#,
+            api_key,
+            limit
+        )
+            .fetch_one(client)
+            .await
+            .map(|record| record.needs_reauth)
+    }
+
+    pub async fn reset_auth_failures(client: &PgPool, api_key: &Uuid) -> Result<(), Error> {
+        sqlx::query!(
+            r#"
+            UPDATE suppliers SET auth_failures = 0
+            WHERE api_key = $1 AND auth_failures > 0
+            "#,
+            api_key
+        )
+            .execute(client)
+            .await?;
+
+        Ok(())
+    }
 }
@@ -0,0 +1,117 @@
+use reqwest::StatusCode;
+use sqlx::migrate::MigrateError;
+use thiserror::Error;
+use tokio::sync::mpsc::error::TrySendError;
+use uuid::Uuid;
+
+#[derive(Error, Debug)]
+pub enum Error {
+    #[error("Failed {context}: {source}")]
+    Db {
+        context: &'static str,
+        #[source]
+        source: sqlx::Error,
+    },
+
+    #[error("Failed run migrations: {0}")]
+    Migrate(#[from] MigrateError),
+
+    #[error("Failed {context}: {source}")]
+    Http {
+        context: &'static str,
+        #[source]
+        source: reqwest::Error,
+    },
+
+    #[error("Failed {context}: {source}")]
+    Io {
+        context: &'static str,
+        #[source]
+        source: std::io::Error,
+    },
+
+    #[error("Failed {context}: {source}")]
+    Json {
+        context: &'static str,
+        #[source]
+        source: serde_json::Error,
+    },
+
+    #[error("Failed decode jwt: {0}")]
+    Jwt(#[from] jsonwebtoken::errors::Error),
+
+    #[error("WB jwt expired")]
+    WbJwtExpired,
+
+    #[error("WB rejected token with status {0}")]
+    WbUnauthorized(StatusCode),
+
+    #[error("WB rate limit exceeded")]
+    WbRateLimited,
+
+    #[error("WB responded with status {0}")]
+    WbStatus(StatusCode),
+
+    #[error("Not available to get many prices without supplier_id")]
+    MissingSupplierId,
+
+    #[error("Plan {plan} allows at most {max} products, would have {total}")]
+    GoodsLimit {
+        plan: String,
+        max: i32,
+        total: i64,
+    },
+
+    #[error("Invalid api key")]
+    InvalidApiKey,
+
+    #[error("Failed request sync: {0}")]
+    SyncQueue(#[from] TrySendError<Uuid>),
+
+    #[error("{0}")]
+    Config(String),
+}
+
+impl Error {
+    pub fn db(context: &'static str) -> impl FnOnce(sqlx::Error) -> Error {
+        move |source| Error::Db { context, source }
+    }
+
+    pub fn http(context: &'static str) -> impl FnOnce(reqwest::Error) -> Error {
+        move |source| Error::Http { context, source }
+    }
+
+    pub fn io(context: &'static str) -> impl FnOnce(std::io::Error) -> Error {
+        move |source| Error::Io { context, source }
+    }
+
+    pub fn json(context: &'static str) -> impl FnOnce(serde_json::Error) -> Error {
+        move |source| Error::Json { context, source }
+    }
+
+    /// The db had no row to read or change.
+    pub fn is_not_found(&self) -> bool {
+        matches!(self, Error::Db { source: sqlx::Error::RowNotFound, .. })
+    }
+
+    /// WB rejected the supplier's token, as opposed to the token expiring.
+    pub fn is_unauthorized(&self) -> bool {
+        matches!(self, Error::WbUnauthorized(_))
+    }
+
+    /// Short stable name stored with failed runs.
+    pub fn class(&self) -> &'static str {
+        match self {
+            Error::Db { .. } => "db",
+            Error::WbJwtExpired => "wb_jwt_expired",
+            Error::WbUnauthorized(_) => "wb_unauthorized",
+            Error::WbRateLimited => "wb_rate_limited",
+            Error::Http { .. }
+            | Error::Io { .. }
+            | Error::Json { .. }
+            | Error::WbStatus(_)
+            | Error::MissingSupplierId => "wb",
+            _ => "internal",
+        }
+    }
+}
@@ -0,0 +1,257 @@
+use std::collections::{HashMap, HashSet};
+use std::io::Cursor;
+use calamine::{Data, Reader, Xlsx};
+use serde::Serialize;
+use crate::db::product::ImportedProduct;
+use crate::utils;
+
+#[derive(Debug, Clone, Copy)]
+pub enum Format {
+    Csv,
+    Xlsx,
+}
+
+impl Format {
+    pub fn detect(file_name: Option<&str>, content_type: Option<&str>) -> Option<Format> {
+        let extension = file_name
+            .and_then(|name| name.rsplit_once('.'))
+            .map(|(_, extension)| extension.to_lowercase());
+
+        match (extension.as_deref(), content_type) {
+            (Some("csv"), _) | (_, Some("text/csv")) => Some(Format::Csv),
+            (Some("xlsx"), _)
+            | (_, Some("application/vnd.openxmlformats-officedocument.spreadsheetml.sheet")) => Some(Format::Xlsx),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct ImportRow {
+    pub row: usize,
+    pub nm_id: Option<i32>,
+    pub vendor_code: Option<String>,
+    pub price: i32,
+    pub min_price: Option<i32>,
+    pub max_price: Option<i32>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct RowError {
+    pub row: usize,
+    pub error: String,
+}
+
+impl RowError {
+    pub fn new(row: usize, error: &str) -> Self {
+        Self { row, error: error.to_string() }
+    }
+}
+
+struct Columns {
+    nm_id: Option<usize>,
+    vendor_code: Option<usize>,
+    price: usize,
+    min_price: Option<usize>,
+    max_price: Option<usize>,
+}
+
+impl Columns {
+    fn from_header(header: &[String]) -> Result<Self, String> {
+        let find = |names: &[&str]| {
+            header.iter().position(|cell| {
+                let cell = cell.trim().to_lowercase().replace([' ', '-'], "_");
+                names.contains(&cell.as_str())
+            })
+        };
+
+        let columns = Columns {
+            nm_id: find(&["nm_id", "nmid", "nm", "id"]),
+            vendor_code: find(&["vendor_code", "vendorcode", "article"]),
+            price: find(&["price", "target_price", "target"])
+                .ok_or_else(|| "Header has no price column".to_string())?,
+            min_price: find(&["min_price", "min"]),
+            max_price: find(&["max_price", "max"]),
+        };
+        if columns.nm_id.is_none() && columns.vendor_code.is_none() {
+            return Err("Header needs nm_id or vendor_code column".to_string());
+        }
+
+        Ok(columns)
+    }
+}
+
+/// Parses the first sheet of a spreadsheet, the first row must be a header.
+/// Rows are numbered like in a spreadsheet app, so the header is row 1.
+pub fn parse(format: Format, data: &[u8]) -> Result<(Vec<ImportRow>, Vec<RowError>), String> {
+    let records = match format {
+        Format::Csv => read_csv(data)?,
+        Format::Xlsx => read_xlsx(data)?,
+    };
+    let mut records = records.into_iter().enumerate();
+
+    let columns = match records.next() {
+        Some((_, header)) => Columns::from_header(&header)?,
+        None => return Err("File is empty".to_string()),
+    };
+
+    let (mut rows, mut errors) = (Vec::new(), Vec::new());
+    let (mut seen_ids, mut seen_codes) = (HashSet::new(), HashSet::new());
+    for (index, record) in records {
+        if record.iter().all(|cell| cell.trim().is_empty()) {
+            continue;
+        }
+
+        match parse_row(index + 1, &columns, &record) {
+            Ok(row) if row.nm_id.is_some_and(|id| !seen_ids.insert(id)) => {
+                errors.push(RowError::new(row.row, "Duplicate nm_id in file"));
+            }
+            Ok(row) if row.vendor_code.as_ref().is_some_and(|code| !seen_codes.insert(code.clone())) => {
+                errors.push(RowError::new(row.row, "Duplicate vendor_code in file"));
+            }
+            Ok(row) => rows.push(row),
+            Err(error) => errors.push(error),
+        }
+    }
+
+    Ok((rows, errors))
+}
+
+fn parse_row(row: usize, columns: &Columns, record: &[String]) -> Result<ImportRow, RowError> {
+    let cell = |column: Option<usize>| {
+        column
+            .and_then(|column| record.get(column))
+            .map(|value| value.trim())
+            .filter(|value| !value.is_empty())
+    };
+    let number = |column: Option<usize>, name: &str| -> Result<Option<i32>, RowError> {
+        cell(column)
+            .map(|value| parse_price(value).ok_or_else(|| RowError::new(row, &format!("{} must be a positive whole number", name))))
+            .transpose()
+    };
+
+    let nm_id = cell(columns.nm_id)
+        .map(|value| parse_price(value).ok_or_else(|| RowError::new(row, "nm_id must be a positive whole number")))
+        .transpose()?;
+    let vendor_code = cell(columns.vendor_code).map(str::to_string);
+    if nm_id.is_none() && vendor_code.is_none() {
+        return Err(RowError::new(row, "Row needs nm_id or vendor_code"));
+    }
+
+    let price = number(Some(columns.price), "price")?
+        .ok_or_else(|| RowError::new(row, "price is required"))?;
+    let min_price = number(columns.min_price, "min_price")?;
+    let max_price = number(columns.max_price, "max_price")?;
+    if min_price.is_some_and(|min| price < min) || max_price.is_some_and(|max| price > max) {
+        return Err(RowError::new(row, "price must be within min_price and max_price"));
+    }
+
+    Ok(ImportRow { row, nm_id, vendor_code, price, min_price, max_price })
+}
+
+/// Turns parsed rows into goods, a row without nm_id needs a good that already has its vendor code.
+pub fn resolve(rows: Vec<ImportRow>, known: &HashMap<String, i32>, errors: &mut Vec<RowError>) -> Vec<ImportedProduct> {
+    let mut seen = HashSet::new();
+    let mut products = Vec::with_capacity(rows.len());
+    for row in rows {
+        let id = row.nm_id.or_else(|| row.vendor_code.as_ref().and_then(|code| known.get(code).copied()));
+        match id {
+            None => errors.push(RowError::new(row.row, "Unknown vendor_code, set nm_id for new goods")),
+            Some(id) if !seen.insert(id) => errors.push(RowError::new(row.row, "Duplicate good in file")),
+            Some(id) => products.push(ImportedProduct {
+                id,
+                price: row.price,
+                vendor_code: row.vendor_code,
+                min_price: row.min_price,
+                max_price: row.max_price,
+            }),
+        }
+    }
+
+    products
+}
+
+// spreadsheets often store whole numbers as floats, so "1290.0" is accepted too
+fn parse_price(value: &str) -> Option<i32> {
+    let value = value.parse::<f64>().ok()?;
+    (value.fract() == 0.0 && value > 0.0 && value <= i32::MAX as f64).then_some(value as i32)
+}
+
+fn read_csv(data: &[u8]) -> Result<Vec<Vec<String>>, String> {
+    // Excel saves CSV with ';' in locales where ',' is the decimal separator
+    let first_line = data.split(|byte| *byte == b'\n').next().unwrap_or_default();
+    let delimiter = if first_line.contains(&b';') { b';' } else { b',' };
+
+    csv::ReaderBuilder::new()
+        .has_headers(false)
+        .flexible(true)
+        .delimiter(delimiter)
+        .from_reader(data.strip_prefix(b"\xEF\xBB\xBF").unwrap_or(data))
+        .records()
+        .map(|record| {
+            record
+                .map(|record| record.iter().map(str::to_string).collect())
+                .map_err(|err| utils::make_err(Box::new(err), "read csv"))
+        })
+        .collect()
+}
+
+fn read_xlsx(data: &[u8]) -> Result<Vec<Vec<String>>, String> {
+    let mut workbook = Xlsx::new(Cursor::new(data))
+        .map_err(|err| utils::make_err(Box::new(err), "open xlsx"))?;
+    let range = workbook
+        .worksheet_range_at(0)
+        .ok_or_else(|| "Workbook has no sheets".to_string())?
+        .map_err(|err| utils::make_err(Box::new(err), "read xlsx sheet"))?;
+
+    Ok(range
+        .rows()
+        .map(|row| {
+            row.iter()
+                .map(|cell| match cell {
+                    Data::Empty => String::new(),
+                    cell => cell.to_string(),
+                })
+                .collect()
+        })
+        .collect())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_semicolon_separated_csv() {
+        let data = "\u{FEFF}nm_id;vendor_code;price;min_price\n101;A-1;1290;\n102;A-2;1290.0;1000\n";
+
+        let (rows, errors) = parse(Format::Csv, data.as_bytes()).unwrap();
+
+        assert!(errors.is_empty());
+        let rows: Vec<_> = rows.iter().map(|row| (row.row, row.nm_id, row.vendor_code.as_deref(), row.price, row.min_price)).collect();
+        assert_eq!(rows, [(2, Some(101), Some("A-1"), 1290, None), (3, Some(102), Some("A-2"), 1290, Some(1000))]);
+    }
+
+    #[test]
+    fn reports_bad_price_row_and_keeps_the_rest() {
+        let data = "nm_id,price\n101,abc\n102,-5\n103,500\n";
+
+        let (rows, errors) = parse(Format::Csv, data.as_bytes()).unwrap();
+
+        assert_eq!(rows.iter().map(|row| row.nm_id).collect::<Vec<_>>(), [Some(103)]);
+        let errors: Vec<_> = errors.iter().map(|error| (error.row, error.error.as_str())).collect();
+        assert_eq!(errors, [(2, "price must be a positive whole number"), (3, "price must be a positive whole number")]);
+    }
+
+    #[test]
+    fn resolves_vendor_codes_and_reports_unknown_ones() {
+        let (rows, mut errors) = parse(Format::Csv, b"nm_id,vendor_code,price\n,A-1,500\n,A-2,600\n103,A-3,700\n").unwrap();
+        let known = HashMap::from([("A-1".to_string(), 101)]);
+
+        let products = resolve(rows, &known, &mut errors);
+
+        assert_eq!(products.iter().map(|product| (product.id, product.price)).collect::<Vec<_>>(), [(101, 500), (103, 700)]);
+        let errors: Vec<_> = errors.iter().map(|error| (error.row, error.error.as_str())).collect();
+        assert_eq!(errors, [(3, "Unknown vendor_code, set nm_id for new goods")]);
+    }
+}
@@ -1,27 +1,43 @@
 mod db;
+mod error;
+mod crypto;
 mod calc;
+mod import;
 mod wb;
 mod state;
 mod utils;
 mod api;
 mod update;
+mod rate_limit;
 
 use std::sync::Arc;
-use tracing::Level;
+use tokio::sync::mpsc;
+use tracing::level_filters::LevelFilter;
+use tracing_subscriber::EnvFilter;
+use crate::crypto::{AdminKeys, TokenCipher};
 use crate::state::AppState;
 
+const SYNC_QUEUE_SIZE: usize = 1024;
+
 #[tokio::main]
 async fn main() -> Result<(), String> {
+    // RUST_LOG overrides, warnings such as rejected admin keys are kept by default
     tracing_subscriber::fmt().json()
-        .with_max_level(Level::ERROR)
-        .with_env_filter(tracing_subscriber::EnvFilter::from_default_env())
+        .with_env_filter(
+            EnvFilter::builder()
+                .with_default_directive(LevelFilter::WARN.into())
+                .from_env_lossy()
+        )
         .init();
 
     let db_url = utils::get_env_var("DATABASE_URL")?;
-    let app_state = Arc::new(AppState::setup_app_state(&db_url)
+    let cipher = TokenCipher::from_env()?;
+    let admin_keys = AdminKeys::from_env()?;
+    let (sync_tx, sync_rx) = mpsc::channel(SYNC_QUEUE_SIZE);
+    let app_state = Arc::new(AppState::setup_app_state(&db_url, cipher, admin_keys, sync_tx)
         .await
         .expect("Failed to build AppState"));
-    app_state.run_migrations().await?;
+    app_state.run_migrations().await.map_err(|err| err.to_string())?;
 
     let api_handle = tokio::spawn({
         let app_state = app_state.clone();
@@ -33,7 +49,7 @@ async fn main() -> Result<(), String> {
     let update_handle = tokio::spawn({
         let app_state = app_state.clone();
         async move {
-            update::run(app_state).await
+            update::run(app_state, sync_rx).await
         }
     });
 
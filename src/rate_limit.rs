@@ -0,0 +1,158 @@
+use std::collections::HashMap;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+// past this many keys expired windows are swept, and new keys are refused if none expired
+const MAX_TRACKED_KEYS: usize = 10_000;
+
+#[derive(Debug, Clone, Copy)]
+pub struct Quota {
+    pub limit: u32,
+    pub remaining: u32,
+    pub reset: Duration,
+}
+
+struct Window {
+    started: Instant,
+    hits: u32,
+}
+
+struct Windows {
+    by_key: HashMap<String, Window>,
+    last_sweep: Instant,
+}
+
+/// Fixed window counters kept in memory, they are per instance and start over on restart.
+pub struct RateLimiter {
+    window: Duration,
+    max_keys: usize,
+    windows: Mutex<Windows>,
+}
+
+impl RateLimiter {
+    pub fn new(window: Duration) -> Self {
+        Self::with_max_keys(window, MAX_TRACKED_KEYS)
+    }
+
+    fn with_max_keys(window: Duration, max_keys: usize) -> Self {
+        Self {
+            window,
+            max_keys,
+            windows: Mutex::new(Windows { by_key: HashMap::new(), last_sweep: Instant::now() }),
+        }
+    }
+
+    /// Counts a hit for the key, `Err` when the limit is already used up in the current window.
+    pub fn hit(&self, key: &str, limit: u32) -> Result<Quota, Quota> {
+        self.hit_at(key, limit, Instant::now())
+    }
+
+    /// What is left for the key without counting a hit.
+    pub fn peek(&self, key: &str, limit: u32) -> Quota {
+        self.peek_at(key, limit, Instant::now())
+    }
+
+    fn hit_at(&self, key: &str, limit: u32, now: Instant) -> Result<Quota, Quota> {
+        let mut windows = self.windows.lock().unwrap_or_else(|err| err.into_inner());
+
+        if !windows.by_key.contains_key(key) && windows.by_key.len() >= self.max_keys {
+            // sweeping is a full scan, so it happens at most once per window
+            let since_sweep = now.duration_since(windows.last_sweep);
+            if since_sweep >= self.window {
+                windows.by_key.retain(|_, window| now.duration_since(window.started) < self.window);
+                windows.last_sweep = now;
+            }
+            if windows.by_key.len() >= self.max_keys {
+                let reset = self.window.saturating_sub(now.duration_since(windows.last_sweep));
+                return Err(Quota { limit, remaining: 0, reset });
+            }
+        }
+
+        let window = windows.by_key.entry(key.to_string()).or_insert(Window { started: now, hits: 0 });
+        if now.duration_since(window.started) >= self.window {
+            *window = Window { started: now, hits: 0 };
+        }
+        if window.hits >= limit {
+            return Err(self.quota(window, limit, now));
+        }
+        window.hits += 1;
+
+        Ok(self.quota(window, limit, now))
+    }
+
+    fn peek_at(&self, key: &str, limit: u32, now: Instant) -> Quota {
+        let windows = self.windows.lock().unwrap_or_else(|err| err.into_inner());
+
+        match windows.by_key.get(key) {
+            Some(window) if now.duration_since(window.started) < self.window => self.quota(window, limit, now),
+            _ => Quota { limit, remaining: limit, reset: self.window },
+        }
+    }
+
+    fn quota(&self, window: &Window, limit: u32, now: Instant) -> Quota {
+        Quota {
+            limit,
+            remaining: limit.saturating_sub(window.hits),
+            reset: self.window.saturating_sub(now.duration_since(window.started)),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const MINUTE: Duration = Duration::from_secs(60);
+
+    #[test]
+    fn refuses_hits_over_limit() {
+        let limiter = RateLimiter::new(MINUTE);
+        let now = Instant::now();
+
+        assert_eq!(limiter.hit_at("a", 2, now).unwrap().remaining, 1);
+        assert_eq!(limiter.hit_at("a", 2, now).unwrap().remaining, 0);
+        let quota = limiter.hit_at("a", 2, now + Duration::from_secs(10)).unwrap_err();
+
+        assert_eq!(quota.remaining, 0);
+        assert_eq!(quota.reset, Duration::from_secs(50));
+        assert!(limiter.hit_at("b", 2, now).is_ok());
+    }
+
+    #[test]
+    fn starts_over_when_window_passes() {
+        let limiter = RateLimiter::new(MINUTE);
+        let now = Instant::now();
+        limiter.hit_at("a", 1, now).unwrap();
+        assert!(limiter.hit_at("a", 1, now + Duration::from_secs(59)).is_err());
+
+        let quota = limiter.hit_at("a", 1, now + MINUTE).unwrap();
+
+        assert_eq!(quota.remaining, 0);
+        assert_eq!(quota.reset, MINUTE);
+    }
+
+    #[test]
+    fn peek_does_not_count() {
+        let limiter = RateLimiter::new(MINUTE);
+        let now = Instant::now();
+
+        assert_eq!(limiter.peek_at("a", 1, now).remaining, 1);
+        assert!(limiter.hit_at("a", 1, now).is_ok());
+        assert_eq!(limiter.peek_at("a", 1, now).remaining, 0);
+        assert_eq!(limiter.peek_at("a", 1, now + MINUTE).remaining, 1);
+    }
+
+    #[test]
+    fn refuses_new_keys_past_cap_until_windows_expire() {
+        let limiter = RateLimiter::with_max_keys(MINUTE, 2);
+        let now = limiter.windows.lock().unwrap().last_sweep;
+        limiter.hit_at("a", 5, now).unwrap();
+        limiter.hit_at("b", 5, now).unwrap();
+
+        assert!(limiter.hit_at("c", 5, now).is_err());
+        assert!(limiter.hit_at("a", 5, now).is_ok());
+
+        assert!(limiter.hit_at("c", 5, now + MINUTE).is_ok());
+        assert_eq!(limiter.windows.lock().unwrap().by_key.len(), 1);
+    }
+}
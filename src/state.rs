@@ -1,79 +1,394 @@
+use std::time::Duration;
+use crate::crypto::{AdminKeys, TokenCipher};
+use crate::error::Error;
 use crate::db::DB;
-use crate::db::product::Product;
-use crate::db::supplier::Supplier;
+use crate::db::product::{GoodSettings, GoodStatus, GoodsFilter, ImportedProduct, PriceBounds, Product, UpsertedProduct};
+use crate::db::run::Run;
+use crate::db::planned_change::PlannedChange;
+use crate::db::schedule::PriceWindow;
+use crate::db::campaign::Campaign;
+use crate::db::freeze::FreezeWindow;
+use crate::db::price_history::{HistoryRecord, ObservedPrice, UploadedPrice};
+use crate::db::audit::{AuditEntry, NewAuditEntry};
+use crate::db::plan::Plan;
+use crate::db::api_key::{ApiKey, IssuedApiKey};
+use crate::db::supplier::{Supplier, SupplierFilter};
+use crate::rate_limit::{Quota, RateLimiter};
+use chrono::{DateTime, Utc};
+use tokio::sync::mpsc::Sender;
+use tracing::info;
 use uuid::Uuid;
-use crate::utils;
+use crate::wb::{PriceChange, PriceUpdate};
+
+// failed admin logins allowed per client ip before it has to wait for the window to pass
+const ADMIN_FAILURE_LIMIT: u32 = 10;
+const ADMIN_FAILURE_WINDOW: Duration = Duration::from_secs(15 * 60);
+const RATE_LIMIT_WINDOW: Duration = Duration::from_secs(60);
 
 pub struct AppState {
     db: DB,
+    sync_tx: Sender<Uuid>,
+    admin_keys: AdminKeys,
+    admin_failures: RateLimiter,
+    rate_limits: RateLimiter,
 }
 
 impl AppState {
-    pub async fn setup_app_state(db_url: &str) -> Result<AppState, String> {
+    pub async fn setup_app_state(
+        db_url: &str,
+        cipher: TokenCipher,
+        admin_keys: AdminKeys,
+        sync_tx: Sender<Uuid>,
+    ) -> Result<AppState, Error> {
         Ok(AppState {
-            db: DB::new(db_url).await?
+            db: DB::new(db_url, cipher).await?,
+            sync_tx,
+            admin_keys,
+            admin_failures: RateLimiter::new(ADMIN_FAILURE_WINDOW),
+            rate_limits: RateLimiter::new(RATE_LIMIT_WINDOW),
         })
     }
 
-    pub async fn run_migrations(&self) -> Result<(), String> {
-        self.db.run_migrations()
-            .await
-            .map_err(|err| utils::make_err(Box::new(err), "create supplier"))
+    pub fn verify_admin_key(&self, key: &str) -> Option<&str> {
+        self.admin_keys.verify(key)
+    }
+
+    /// How long the client has to wait before trying an admin key again.
+    pub fn admin_lockout(&self, client: &str) -> Option<Duration> {
+        let quota = self.admin_failures.peek(client, ADMIN_FAILURE_LIMIT);
+        (quota.remaining == 0).then_some(quota.reset)
+    }
+
+    pub fn register_admin_failure(&self, client: &str) {
+        let _ = self.admin_failures.hit(client, ADMIN_FAILURE_LIMIT);
+    }
+
+    /// Counts a request against a per minute limit, `Err` when it's used up.
+    pub fn hit_rate_limit(&self, key: &str, per_minute: u32) -> Result<Quota, Quota> {
+        self.rate_limits.hit(key, per_minute)
     }
 
-    pub async fn get_supplier(&self, api_key: &Uuid) -> Result<Supplier, String> {
-        match self.db.get_supplier(api_key).await {
-            Ok(Some(supplier)) => Ok(supplier),
-            Ok(None) => Err("Invalid api key".to_string()),
-            Err(err) => Err(utils::make_err(Box::new(err), "get supplier"))
+    pub fn request_sync(&self, api_key: &Uuid) -> Result<(), Error> {
+        Ok(self.sync_tx.try_send(*api_key)?)
+    }
+
+    pub async fn run_migrations(&self) -> Result<(), Error> {
+        let encrypted = self.db.run_migrations().await?;
+        if encrypted > 0 {
+            info!("Encrypted {} plaintext wb jwts", encrypted);
+        }
+
+        let rewrapped = self.db.rewrap_wb_jwts().await?;
+        if rewrapped > 0 {
+            info!("Rewrapped {} wb jwts with the active key", rewrapped);
         }
+
+        Ok(())
+    }
+
+    pub async fn get_supplier(&self, api_key: &Uuid) -> Result<Supplier, Error> {
+        self.db.get_supplier(api_key)
+            .await?
+            .ok_or(Error::InvalidApiKey)
+    }
+
+    /// Resolves the supplier behind a key sent by a client, along with the id of that key.
+    pub async fn authenticate(&self, key: &Uuid) -> Result<(Supplier, i64), Error> {
+        let owner = self.db.authenticate(key)
+            .await?
+            .ok_or(Error::InvalidApiKey)?;
+
+        Ok((self.get_supplier(&owner.supplier_api_key).await?, owner.id))
+    }
+
+    pub async fn create_supplier(&self, label: &str) -> Result<(Supplier, IssuedApiKey), Error> {
+        self.db.create_supplier(label).await
+    }
+
+    pub async fn set_wb_jwt(&self, api_key: &Uuid, jwt: &str) -> Result<(), Error> {
+        self.db.set_wb_jwt(api_key, jwt).await
+    }
+
+    pub async fn get_suppliers(&self, limit: usize, page: usize) -> Result<Vec<Result<Supplier, Error>>, Error> {
+        self.db.get_suppliers(limit, page).await
+    }
+
+    pub async fn set_wb_id(&self, api_key: &Uuid, wb_id: i32) -> Result<(), Error> {
+        self.db.set_wb_id(api_key, wb_id).await
+    }
+
+    pub async fn set_dry_run(&self, api_key: &Uuid, dry_run: bool) -> Result<(), Error> {
+        self.db.set_dry_run(api_key, dry_run).await
+    }
+
+    pub async fn set_supplier_active(&self, api_key: &Uuid, active: bool) -> Result<(), Error> {
+        self.db.set_supplier_active(api_key, active).await
+    }
+
+    pub async fn set_timezone(&self, api_key: &Uuid, timezone: &str) -> Result<(), Error> {
+        self.db.set_timezone(api_key, timezone).await
+    }
+
+    pub async fn set_plan(&self, api_key: &Uuid, plan: &str) -> Result<(), Error> {
+        self.db.set_plan(api_key, plan).await
+    }
+
+    pub async fn get_plan(&self, id: &str) -> Result<Option<Plan>, Error> {
+        self.db.get_plan(id).await
+    }
+
+    pub async fn register_auth_failure(&self, api_key: &Uuid, limit: i32) -> Result<bool, Error> {
+        self.db.register_auth_failure(api_key, limit).await
+    }
+
+    pub async fn reset_auth_failures(&self, api_key: &Uuid) -> Result<(), Error> {
+        self.db.reset_auth_failures(api_key).await
+    }
+
+    pub async fn search_suppliers(&self, filter: &SupplierFilter, limit: usize, page: usize) -> Result<Vec<Supplier>, Error> {
+        self.db.search_suppliers(filter, limit, page).await
+    }
+
+    pub async fn set_supplier_disabled(&self, api_key: &Uuid, disabled: bool) -> Result<bool, Error> {
+        found(self.db.set_supplier_disabled(api_key, disabled).await)
+            .map(|updated| updated.is_some())
+    }
+
+    pub async fn create_api_key(
+        &self,
+        api_key: &Uuid,
+        label: &str,
+        expires_at: Option<DateTime<Utc>>,
+    ) -> Result<IssuedApiKey, Error> {
+        self.db.create_api_key(api_key, label, expires_at).await
+    }
+
+    pub async fn reissue_api_key(&self, api_key: &Uuid, label: &str) -> Result<IssuedApiKey, Error> {
+        self.db.reissue_api_key(api_key, label).await
+    }
+
+    pub async fn get_api_keys(&self, api_key: &Uuid) -> Result<Vec<ApiKey>, Error> {
+        self.db.get_api_keys(api_key).await
+    }
+
+    pub async fn revoke_api_key(&self, api_key: &Uuid, id: i64) -> Result<bool, Error> {
+        found(self.db.revoke_api_key(api_key, id).await)
+            .map(|revoked| revoked.is_some())
+    }
+
+    pub async fn revoke_api_keys(&self, api_key: &Uuid) -> Result<u64, Error> {
+        self.db.revoke_api_keys(api_key).await
+    }
+
+    pub async fn delete_supplier(&self, api_key: &Uuid) -> Result<bool, Error> {
+        found(self.db.delete_supplier(api_key).await)
+            .map(|deleted| deleted.is_some())
+    }
+
+    pub async fn add_goods(&self, api_key: &Uuid, products: &[Product], plan: &Plan) -> Result<Vec<UpsertedProduct>, Error> {
+        self.db.add_goods(api_key, products, plan).await
+    }
+
+    pub async fn get_goods(&self, api_key: &Uuid, active: Option<bool>) -> Result<Vec<Product>, Error> {
+        self.db.get_goods(api_key, active).await
     }
 
-    pub async fn create_supplier(&self) -> Result<Supplier, String> {
-        self.db.create_supplier()
-            .await
-            .map_err(|err| utils::make_err(Box::new(err), "create supplier"))
+    pub async fn set_goods_active(&self, api_key: &Uuid, ids: Option<&[i32]>, active: bool) -> Result<u64, Error> {
+        self.db.set_goods_active(api_key, ids, active).await
     }
 
-    pub async fn set_wb_jwt(&self, api_key: &Uuid, jwt: &str) -> Result<(), String> {
-        self.db.set_wb_jwt(api_key, jwt)
-            .await
-            .map_err(|err| utils::make_err(Box::new(err), "set wb_jwt"))
+    pub async fn get_good(&self, api_key: &Uuid, id: i32) -> Result<Option<Product>, Error> {
+        self.db.get_good(api_key, id).await
     }
 
-    pub async fn get_suppliers(&self, limit: usize, page: usize) -> Result<Vec<Supplier>, String> {
-        self.db.get_suppliers(limit, page)
-            .await
-            .map_err(|err| utils::make_err(Box::new(err), "get suppliers"))
+    pub async fn get_good_settings(&self, api_key: &Uuid, id: i32) -> Result<Option<GoodSettings>, Error> {
+        self.db.get_good_settings(api_key, id).await
     }
 
-    pub async fn set_wb_id(&self, api_key: &Uuid, wb_id: i32) -> Result<(), String> {
-        self.db.set_wb_id(api_key, wb_id)
-            .await
-            .map_err(|err| utils::make_err(Box::new(err), "set wb id"))
+    pub async fn save_good_settings(&self, api_key: &Uuid, good: &GoodSettings, plan: &Plan) -> Result<Option<GoodSettings>, Error> {
+        self.db.save_good_settings(api_key, good, plan).await
     }
 
-    pub async fn add_goods(&self, api_key: &Uuid, products: &Vec<Product>) -> Result<(), String> {
-        self.db.add_goods(api_key, products)
-            .await
-            .map_err(|err| utils::make_err(Box::new(err), "add goods"))
+    pub async fn update_good_settings(&self, api_key: &Uuid, old: &GoodSettings, good: &GoodSettings) -> Result<bool, Error> {
+        found(self.db.update_good_settings(api_key, old, good).await)
+            .map(|updated| updated.is_some())
     }
 
-    pub async fn get_goods(&self, api_key: &Uuid) -> Result<Vec<Product>, String> {
-        self.db.get_goods(api_key)
-            .await
-            .map_err(|err| utils::make_err(Box::new(err), "get goods"))
+    pub async fn count_by_apikey(&self, api_key: &Uuid) -> Result<i64, Error> {
+        self.db.count_by_apikey(api_key).await
     }
 
-    pub async fn count_by_apikey(&self, api_key: &Uuid) -> Result<i64, String> {
-        self.db.count_by_apikey(api_key)
-            .await
-            .map_err(|err| utils::make_err(Box::new(err), "count by apikey"))
+    pub async fn get_goods_status(&self, api_key: &Uuid, filter: &GoodsFilter, limit: usize, page: usize) -> Result<Vec<GoodStatus>, Error> {
+        self.db.get_goods_status(api_key, filter, limit, page).await
     }
 
-    pub async fn delete_by_id_and_api_key(&self, id: i32, api_key: &Uuid) -> Result<(), String> {
-        self.db.delete_by_id_and_api_key(id, api_key)
-            .await
-            .map_err(|err| utils::make_err(Box::new(err), "delete by id and apikey"))
+    pub async fn set_goods_errors(&self, api_key: &Uuid, update: &PriceUpdate) -> Result<(), Error> {
+        self.db.set_goods_errors(api_key, update).await
+    }
+
+    pub async fn import_goods(&self, api_key: &Uuid, products: &[ImportedProduct], plan: &Plan) -> Result<Vec<UpsertedProduct>, Error> {
+        self.db.import_goods(api_key, products, plan).await
+    }
+
+    pub async fn find_goods_by_vendor_codes(&self, api_key: &Uuid, vendor_codes: &[String]) -> Result<Vec<(String, i32)>, Error> {
+        self.db.find_goods_by_vendor_codes(api_key, vendor_codes).await
+    }
+
+    pub async fn get_price_bounds(&self, api_key: &Uuid) -> Result<Vec<PriceBounds>, Error> {
+        self.db.get_price_bounds(api_key).await
+    }
+
+    pub async fn delete_by_id_and_api_key(&self, id: i32, api_key: &Uuid) -> Result<bool, Error> {
+        found(self.db.delete_by_id_and_api_key(id, api_key).await)
+            .map(|deleted| deleted.is_some())
+    }
+
+    pub async fn start_run(
+        &self,
+        parent_id: Option<i64>,
+        api_key: Option<&Uuid>,
+        dry_run: bool,
+        frozen: bool,
+    ) -> Result<i64, Error> {
+        self.db.start_run(parent_id, api_key, dry_run, frozen).await
+    }
+
+    pub async fn finish_run(
+        &self,
+        id: i64,
+        products_checked: i32,
+        products_changed: i32,
+        error_class: Option<&str>,
+        error_message: Option<&str>,
+    ) -> Result<(), Error> {
+        self.db.finish_run(id, products_checked, products_changed, error_class, error_message).await
+    }
+
+    pub async fn get_last_run_started_at(&self, api_key: &Uuid) -> Result<Option<DateTime<Utc>>, Error> {
+        self.db.get_last_run_started_at(api_key).await
+    }
+
+    pub async fn get_runs(&self, api_key: &Uuid, limit: usize, page: usize) -> Result<Vec<Run>, Error> {
+        self.db.get_runs(api_key, limit, page).await
+    }
+
+    pub async fn add_planned_changes(&self, api_key: &Uuid, run_id: Option<i64>, frozen: bool, changes: &[PriceChange]) -> Result<(), Error> {
+        self.db.add_planned_changes(api_key, run_id, frozen, changes).await
+    }
+
+    pub async fn get_planned_changes(&self, api_key: &Uuid, frozen: bool, limit: usize, page: usize) -> Result<Vec<PlannedChange>, Error> {
+        self.db.get_planned_changes(api_key, frozen, limit, page).await
+    }
+
+    pub async fn get_schedules(&self, api_key: &Uuid) -> Result<Vec<PriceWindow>, Error> {
+        self.db.get_schedules(api_key).await
+    }
+
+    pub async fn get_schedule(&self, api_key: &Uuid, product_id: i32) -> Result<Vec<PriceWindow>, Error> {
+        self.db.get_schedule(api_key, product_id).await
+    }
+
+    pub async fn set_schedule(&self, api_key: &Uuid, product_id: i32, windows: &[PriceWindow]) -> Result<bool, Error> {
+        found(self.db.set_schedule(api_key, product_id, windows).await)
+            .map(|updated| updated.is_some())
+    }
+
+    pub async fn create_campaign(
+        &self,
+        api_key: &Uuid,
+        name: &str,
+        starts_at: DateTime<Utc>,
+        ends_at: DateTime<Utc>,
+        products: &[Product],
+    ) -> Result<Option<Campaign>, Error> {
+        found(self.db.create_campaign(api_key, name, starts_at, ends_at, products).await)
+    }
+
+    pub async fn get_campaigns(&self, api_key: &Uuid) -> Result<Vec<Campaign>, Error> {
+        self.db.get_campaigns(api_key).await
+    }
+
+    pub async fn get_campaign(&self, api_key: &Uuid, id: i64) -> Result<Option<(Campaign, Vec<Product>)>, Error> {
+        self.db.get_campaign(api_key, id).await
+    }
+
+    pub async fn get_campaign_prices(&self, api_key: &Uuid, at: DateTime<Utc>) -> Result<Vec<Product>, Error> {
+        self.db.get_campaign_prices(api_key, at).await
+    }
+
+    pub async fn delete_campaign(&self, api_key: &Uuid, id: i64) -> Result<bool, Error> {
+        found(self.db.delete_campaign(api_key, id).await)
+            .map(|deleted| deleted.is_some())
+    }
+
+    pub async fn create_freeze(
+        &self,
+        api_key: &Uuid,
+        starts_at: DateTime<Utc>,
+        ends_at: DateTime<Utc>,
+        reason: Option<&str>,
+    ) -> Result<FreezeWindow, Error> {
+        self.db.create_freeze(api_key, starts_at, ends_at, reason).await
+    }
+
+    pub async fn get_freezes(&self, api_key: &Uuid) -> Result<Vec<FreezeWindow>, Error> {
+        self.db.get_freezes(api_key).await
+    }
+
+    pub async fn get_active_freeze(&self, api_key: &Uuid, at: DateTime<Utc>) -> Result<Option<FreezeWindow>, Error> {
+        self.db.get_active_freeze(api_key, at).await
+    }
+
+    pub async fn delete_freeze(&self, api_key: &Uuid, id: i64) -> Result<bool, Error> {
+        found(self.db.delete_freeze(api_key, id).await)
+            .map(|deleted| deleted.is_some())
+    }
+
+    pub async fn record_price_history(&self, api_key: &Uuid, update: &PriceUpdate) -> Result<(), Error> {
+        self.db.record_price_history(api_key, update).await
+    }
+
+    pub async fn get_price_history(
+        &self,
+        api_key: &Uuid,
+        product_id: i32,
+        from: DateTime<Utc>,
+        to: DateTime<Utc>,
+        bucket_secs: Option<i64>,
+    ) -> Result<(Vec<ObservedPrice>, Vec<UploadedPrice>), Error> {
+        self.db.get_price_history(api_key, product_id, from, to, bucket_secs).await
+    }
+
+    pub async fn get_history_after(
+        &self,
+        api_key: &Uuid,
+        product_id: Option<i32>,
+        from: DateTime<Utc>,
+        to: DateTime<Utc>,
+        after_id: i64,
+        limit: usize,
+    ) -> Result<Vec<HistoryRecord>, Error> {
+        self.db.get_history_after(api_key, product_id, from, to, after_id, limit).await
+    }
+
+    pub async fn prune_price_history(&self, before: DateTime<Utc>) -> Result<u64, Error> {
+        self.db.prune_price_history(before).await
+    }
+
+    pub async fn add_audit_entry(&self, entry: &NewAuditEntry) -> Result<(), Error> {
+        self.db.add_audit_entry(entry).await
+    }
+
+    pub async fn get_audit_log(&self, api_key: &Uuid, limit: usize, page: usize) -> Result<Vec<AuditEntry>, Error> {
+        self.db.get_audit_log(api_key, limit, page).await
+    }
+}
+
+// RowNotFound from db means the caller asked for something that isn't there, not a failure
+fn found<T>(result: Result<T, Error>) -> Result<Option<T>, Error> {
+    match result {
+        Ok(value) => Ok(Some(value)),
+        Err(err) if err.is_not_found() => Ok(None),
+        Err(err) => Err(err),
     }
 }
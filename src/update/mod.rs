@@ -1,42 +1,266 @@
+mod targets;
+
+use std::collections::HashSet;
 use std::sync::Arc;
 use std::time::Duration;
-use log::{info, warn};
-use tokio::time::sleep;
+use chrono::Utc;
+use chrono_tz::Tz;
+use log::{error, info, warn};
+use tokio::sync::mpsc::Receiver;
+use tokio::time::{interval, MissedTickBehavior};
+use uuid::Uuid;
+use crate::db::product::Product;
+use crate::db::supplier::Supplier;
+use crate::error::Error;
 use crate::state::AppState;
-use crate::wb::calculate_and_set_price;
+use crate::utils;
+use crate::wb::{calculate_and_set_price, PriceUpdate};
+
+const PAUSE: u64 = 60;
+// ticks drift by however long the suppliers before this one took, don't skip a tick over that
+const COOLDOWN_SLACK_MS: i64 = 5_000;
+pub const AUTH_FAILURES_LIMIT: i32 = 3;
+
+pub async fn run(state: Arc<AppState>, mut sync_rx: Receiver<Uuid>) -> Result<(), Error> {
+    let retention_days = utils::get_env_or("PRICE_HISTORY_RETENTION_DAYS", "90".to_string())
+        .map_err(Error::Config)?
+        .parse::<i64>()
+        .map_err(|_| Error::Config("PRICE_HISTORY_RETENTION_DAYS must be a number of days".to_string()))?;
 
-const PAUSE: u64 = 1 * 60;
+    let mut ticker = interval(Duration::from_secs(PAUSE));
+    ticker.set_missed_tick_behavior(MissedTickBehavior::Delay);
 
-pub async fn run(state: Arc<AppState>) -> Result<(), String> {
     loop {
-        let suppliers = match state.get_suppliers(300, 1).await {
-            Ok(suppliers) => suppliers,
+        tokio::select! {
+            _ = ticker.tick() => {
+                // a failed cycle is retried on the next tick instead of stopping the updater
+                if let Err(e) = update_all(&state).await {
+                    error!("Failed update cycle: {}", e);
+                }
+                prune_history(&state, retention_days).await;
+            }
+            Some(api_key) = sync_rx.recv() => {
+                let mut api_keys = HashSet::from([api_key]);
+                while let Ok(api_key) = sync_rx.try_recv() {
+                    api_keys.insert(api_key);
+                }
+
+                for api_key in api_keys {
+                    sync_supplier(&state, &api_key).await;
+                }
+            }
+        }
+    }
+}
+
+async fn update_all(state: &AppState) -> Result<(), Error> {
+    let cycle_id = state.start_run(None, None, false, false).await?;
+
+    let suppliers = match state.get_suppliers(300, 1).await {
+        Ok(suppliers) => suppliers,
+        Err(e) => {
+            finish_run(state, cycle_id, 0, 0, Some(("suppliers", &e.to_string()))).await;
+            return Err(e);
+        }
+    };
+
+    let (mut checked, mut changed) = (0, 0);
+    for supplier in suppliers {
+        let supplier = match supplier {
+            Ok(supplier) => supplier,
             Err(e) => {
-                return Err(format!("Failed to get suppliers: {}", e));
+                error!("Skip supplier: {}", e);
+                continue;
             }
         };
+        if let Some((supplier_checked, supplier_changed)) = update_and_record(state, &supplier, Some(cycle_id)).await {
+            checked += supplier_checked;
+            changed += supplier_changed;
+        }
+    }
+
+    finish_run(state, cycle_id, checked, changed, None).await;
+    info!("Sleeping for {} seconds", PAUSE);
+
+    Ok(())
+}
+
+async fn prune_history(state: &AppState, retention_days: i64) {
+    match state.prune_price_history(Utc::now() - chrono::Duration::days(retention_days)).await {
+        Ok(0) => {}
+        Ok(deleted) => info!("Pruned {} price history rows", deleted),
+        Err(e) => error!("Failed to prune price history: {}", e),
+    }
+}
+
+async fn sync_supplier(state: &AppState, api_key: &Uuid) {
+    match state.get_supplier(api_key).await {
+        Ok(supplier) => {
+            info!("Sync requested for supplier {}", supplier);
+            update_and_record(state, &supplier, None).await;
+        }
+        Err(e) => error!("Failed to get supplier {} for sync: {}", api_key, e),
+    }
+}
+
+async fn update_and_record(state: &AppState, supplier: &Supplier, parent_id: Option<i64>) -> Option<(i32, i32)> {
+    let wb_jwt = supplier.wb_jwt.as_ref()?;
+    if supplier.disabled {
+        info!("Skip supplier {}: disabled by admin", supplier);
+        return None;
+    }
+    if !supplier.active {
+        info!("Skip supplier {}: paused", supplier);
+        return None;
+    }
+    if supplier.needs_reauth {
+        info!("Skip supplier {}: needs new wb jwt", supplier);
+        return None;
+    }
+    if utils::is_jwt_expired(wb_jwt) {
+        info!("Skip supplier {}: wb jwt expired", supplier);
+        return None;
+    }
+
+    // an explicit sync (no parent cycle) was already accepted by the api, only scheduled runs wait
+    if parent_id.is_some() {
+        if let Some(wait) = plan_cooldown(state, supplier).await {
+            info!("Skip supplier {}: plan allows next update in {} seconds", supplier, wait);
+            return None;
+        }
+    }
 
-        for supplier in suppliers {
-            if let Some(wb_jwt) = supplier.wb_jwt.as_ref() {
-                let goods = match state.get_goods(&supplier.api_key).await {
-                    Ok(goods) => goods,
-                    Err(e) => {
-                        warn!("Failed to fetch goods for supplier {}: {}", supplier.api_key, e);
-                        continue;
-                    }
-                };
+    let frozen = match state.get_active_freeze(&supplier.api_key, Utc::now()).await {
+        Ok(freeze) => freeze.is_some(),
+        Err(e) => {
+            error!("Failed to check freeze windows for supplier {}: {}", supplier, e);
+            return None;
+        }
+    };
+
+    let run_id = match state.start_run(parent_id, Some(&supplier.api_key), supplier.dry_run, frozen).await {
+        Ok(run_id) => run_id,
+        Err(e) => {
+            error!("Failed to start run for supplier {}: {}", supplier.api_key, e);
+            return None;
+        }
+    };
 
-                if let Err(err) = calculate_and_set_price(
-                    supplier.wb_id,
-                    wb_jwt,
-                    goods
-                ).await {
-                    warn!("Failed background update sid={:?}: {}", supplier.wb_id, err)
-                };
+    match update_supplier(state, supplier, wb_jwt, supplier.dry_run || frozen).await {
+        Ok(update) => {
+            if frozen && !update.changes.is_empty() {
+                info!("Supplier {} is frozen, {} products drifted from target", supplier, update.changes.len());
+            }
+            if let Err(e) = state.record_price_history(&supplier.api_key, &update).await {
+                error!("Failed to record price history for supplier {}: {}", supplier, e);
+            }
+            if let Err(e) = state.set_goods_errors(&supplier.api_key, &update).await {
+                error!("Failed to save goods errors for supplier {}: {}", supplier, e);
             }
+            if supplier.dry_run || frozen {
+                if let Err(e) = state.add_planned_changes(&supplier.api_key, Some(run_id), frozen, &update.changes).await {
+                    error!("Failed to save planned changes for supplier {}: {}", supplier, e);
+                }
+            }
+            let (checked, changed) = (update.checked as i32, update.changes.len() as i32);
+            finish_run(state, run_id, checked, changed, None).await;
+            Some((checked, changed))
+        }
+        Err(err) => {
+            warn!("Failed background update sid={:?}: {}", supplier.wb_id, err);
+            finish_run(state, run_id, 0, 0, Some((err.class(), &err.to_string()))).await;
+            None
         }
+    }
+}
+
+async fn plan_cooldown(state: &AppState, supplier: &Supplier) -> Option<i64> {
+    let plan = match state.get_plan(&supplier.plan).await {
+        Ok(Some(plan)) => plan,
+        Ok(None) => {
+            error!("Unknown plan {} of supplier {}", supplier.plan, supplier);
+            return None;
+        }
+        Err(e) => {
+            error!("Failed to get plan of supplier {}: {}", supplier, e);
+            return None;
+        }
+    };
+    if plan.min_update_interval <= 0 {
+        return None;
+    }
+
+    let last_started_at = match state.get_last_run_started_at(&supplier.api_key).await {
+        Ok(last_started_at) => last_started_at?,
+        Err(e) => {
+            error!("Failed to get last run of supplier {}: {}", supplier, e);
+            return None;
+        }
+    };
+    let elapsed = (Utc::now() - last_started_at).num_milliseconds() + COOLDOWN_SLACK_MS;
+    let wait = plan.min_update_interval as i64 * 1000 - elapsed;
+
+    // whole seconds, rounded up
+    (wait > 0).then_some((wait + 999) / 1000)
+}
+
+async fn update_supplier(
+    state: &AppState,
+    supplier: &Supplier,
+    wb_jwt: &str,
+    dry_run: bool,
+) -> Result<PriceUpdate, Error> {
+    let goods = state.get_goods(&supplier.api_key, Some(true)).await?;
+    let goods = target_prices(state, supplier, goods).await?;
+
+    match calculate_and_set_price(supplier.wb_id, wb_jwt, goods, dry_run).await {
+        Ok(update) => {
+            if supplier.auth_failures > 0 {
+                if let Err(e) = state.reset_auth_failures(&supplier.api_key).await {
+                    error!("Failed to reset auth failures for supplier {}: {}", supplier, e);
+                }
+            }
+            Ok(update)
+        }
+        Err(err) => {
+            if err.is_unauthorized() {
+                register_auth_failure(state, supplier).await;
+            }
+            Err(err)
+        }
+    }
+}
+
+/// Replaces stored prices with the ones the updater would upload right now:
+/// schedules, then campaigns, then min/max bounds.
+pub async fn target_prices(state: &AppState, supplier: &Supplier, goods: Vec<Product>) -> Result<Vec<Product>, Error> {
+    let schedules = state.get_schedules(&supplier.api_key).await?;
+
+    let tz = supplier.timezone.parse::<Tz>().unwrap_or_else(|_| {
+        warn!("Unknown timezone {} of supplier {}, fallback to UTC", supplier.timezone, supplier);
+        Tz::UTC
+    });
+    let now = Utc::now();
+    let campaign_prices = state.get_campaign_prices(&supplier.api_key, now).await?;
+    let bounds = state.get_price_bounds(&supplier.api_key).await?;
+
+    let goods = targets::apply_schedules(goods, &schedules, tz, now);
+    let goods = targets::apply_campaigns(goods, &campaign_prices);
+
+    Ok(targets::apply_bounds(goods, &bounds))
+}
+
+pub async fn register_auth_failure(state: &AppState, supplier: &Supplier) {
+    match state.register_auth_failure(&supplier.api_key, AUTH_FAILURES_LIMIT).await {
+        Ok(true) => warn!("Supplier {} needs new wb jwt", supplier),
+        Ok(false) => {}
+        Err(e) => error!("Failed to register auth failure for supplier {}: {}", supplier, e),
+    }
+}
 
-        info!("Sleeping for {} seconds", PAUSE);
-        sleep(Duration::from_secs(PAUSE)).await;
+async fn finish_run(state: &AppState, id: i64, checked: i32, changed: i32, err: Option<(&str, &str)>) {
+    let (error_class, error_message) = err.unzip();
+    if let Err(e) = state.finish_run(id, checked, changed, error_class, error_message).await {
+        error!("Failed to finish run {}: {}", id, e);
     }
 }
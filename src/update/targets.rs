@@ -0,0 +1,165 @@
+use std::collections::HashMap;
+use chrono::{DateTime, Datelike, Days, NaiveDateTime, Utc};
+use chrono_tz::Tz;
+use crate::db::product::{PriceBounds, Product};
+use crate::db::schedule::PriceWindow;
+
+pub fn apply_schedules(goods: Vec<Product>, windows: &[PriceWindow], tz: Tz, now: DateTime<Utc>) -> Vec<Product> {
+    let local = now.with_timezone(&tz).naive_local();
+
+    let mut by_product: HashMap<i32, Vec<&PriceWindow>> = HashMap::new();
+    for window in windows {
+        by_product.entry(window.product_id).or_default().push(window);
+    }
+
+    goods
+        .into_iter()
+        .map(|product| {
+            let scheduled = by_product
+                .get(&product.id)
+                .and_then(|windows| windows.iter().find(|window| is_active(window, local)));
+
+            match scheduled {
+                Some(window) => Product::new(product.id, window.price),
+                None => product,
+            }
+        })
+        .collect()
+}
+
+pub fn apply_campaigns(goods: Vec<Product>, overrides: &[Product]) -> Vec<Product> {
+    let overrides: HashMap<i32, i32> = overrides.iter().map(|p| (p.id, p.price)).collect();
+
+    goods
+        .into_iter()
+        .map(|product| match overrides.get(&product.id) {
+            Some(price) => Product::new(product.id, *price),
+            None => product,
+        })
+        .collect()
+}
+
+/// Keeps schedule and campaign overrides within the good's min/max price.
+pub fn apply_bounds(goods: Vec<Product>, bounds: &[PriceBounds]) -> Vec<Product> {
+    let bounds: HashMap<i32, &PriceBounds> = bounds.iter().map(|b| (b.id, b)).collect();
+
+    goods
+        .into_iter()
+        .map(|product| match bounds.get(&product.id) {
+            Some(bounds) => {
+                let price = bounds.min_price.map_or(product.price, |min| product.price.max(min));
+                let price = bounds.max_price.map_or(price, |max| price.min(max));
+                Product::new(product.id, price)
+            }
+            None => product,
+        })
+        .collect()
+}
+
+fn is_active(window: &PriceWindow, local: NaiveDateTime) -> bool {
+    let time = local.time();
+    let overnight = window.start_time >= window.end_time;
+
+    let in_time = if overnight {
+        time >= window.start_time || time < window.end_time
+    } else {
+        time >= window.start_time && time < window.end_time
+    };
+    if !in_time {
+        return false;
+    }
+
+    // after midnight an overnight window still belongs to the day it started
+    let day = if overnight && time < window.end_time {
+        match local.date().checked_sub_days(Days::new(1)) {
+            Some(day) => day,
+            None => return false,
+        }
+    } else {
+        local.date()
+    };
+
+    let weekday = day.weekday().number_from_monday() as i16;
+    let excluded = window.weekdays.as_ref().is_some_and(|weekdays| !weekdays.contains(&weekday))
+        || window.start_date.is_some_and(|start| day < start)
+        || window.end_date.is_some_and(|end| day > end);
+
+    !excluded
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::{NaiveDate, NaiveTime, TimeZone};
+    use super::*;
+
+    fn window(start: &str, end: &str, weekdays: Option<Vec<i16>>, price: i32) -> PriceWindow {
+        PriceWindow {
+            product_id: 1,
+            weekdays,
+            start_date: None,
+            end_date: None,
+            start_time: NaiveTime::parse_from_str(start, "%H:%M").unwrap(),
+            end_time: NaiveTime::parse_from_str(end, "%H:%M").unwrap(),
+            price,
+        }
+    }
+
+    fn prices(goods: Vec<Product>) -> Vec<(i32, i32)> {
+        goods.into_iter().map(|product| (product.id, product.price)).collect()
+    }
+
+    fn moscow(day: u32, hour: u32) -> DateTime<Utc> {
+        let local = NaiveDate::from_ymd_opt(2024, 11, day).unwrap().and_hms_opt(hour, 0, 0).unwrap();
+        chrono_tz::Europe::Moscow.from_local_datetime(&local).unwrap().with_timezone(&Utc)
+    }
+
+    #[test]
+    fn overnight_window_belongs_to_the_day_it_started() {
+        // friday 22:00 to saturday 06:00 in Moscow
+        let windows = [window("22:00", "06:00", Some(vec![5]), 100)];
+        let goods = || vec![Product::new(1, 500)];
+
+        // 01:00 saturday in Moscow is still friday 22:00 in UTC, the window is judged by local time
+        let saturday_night = apply_schedules(goods(), &windows, chrono_tz::Europe::Moscow, moscow(2, 1));
+        let saturday_evening = apply_schedules(goods(), &windows, chrono_tz::Europe::Moscow, moscow(2, 23));
+        let friday_morning = apply_schedules(goods(), &windows, chrono_tz::Europe::Moscow, moscow(1, 1));
+
+        assert_eq!(prices(saturday_night), [(1, 100)]);
+        assert_eq!(prices(saturday_evening), [(1, 500)]);
+        assert_eq!(prices(friday_morning), [(1, 500)]);
+    }
+
+    #[test]
+    fn window_with_equal_start_and_end_lasts_all_day() {
+        let windows = [window("10:00", "10:00", None, 100)];
+
+        for hour in [0, 9, 10, 23] {
+            let goods = apply_schedules(vec![Product::new(1, 500)], &windows, chrono_tz::Europe::Moscow, moscow(1, hour));
+            assert_eq!(prices(goods), [(1, 100)], "at {}:00", hour);
+        }
+    }
+
+    #[test]
+    fn campaign_overrides_schedule() {
+        let windows = [window("00:00", "00:00", None, 100)];
+        let goods = vec![Product::new(1, 500), Product::new(2, 600)];
+
+        let goods = apply_schedules(goods, &windows, chrono_tz::Europe::Moscow, moscow(1, 12));
+        let goods = apply_campaigns(goods, &[Product::new(1, 80)]);
+
+        assert_eq!(prices(goods), [(1, 80), (2, 600)]);
+    }
+
+    #[test]
+    fn bounds_clamp_to_min_and_max() {
+        let goods = vec![Product::new(1, 50), Product::new(2, 900), Product::new(3, 500), Product::new(4, 10)];
+        let bounds = [
+            PriceBounds { id: 1, min_price: Some(100), max_price: Some(800) },
+            PriceBounds { id: 2, min_price: Some(100), max_price: Some(800) },
+            PriceBounds { id: 3, min_price: Some(100), max_price: Some(800) },
+            PriceBounds { id: 4, min_price: None, max_price: None },
+        ];
+
+        assert_eq!(prices(apply_bounds(goods, &bounds)), [(1, 100), (2, 800), (3, 500), (4, 10)]);
+    }
+}
@@ -1,6 +1,8 @@
 use std::env;
+use std::time::{SystemTime, UNIX_EPOCH};
 use jsonwebtoken::{decode, DecodingKey, Validation};
 use serde::Deserialize;
+use crate::error::Error;
 
 pub fn make_err(err: Box<dyn std::error::Error>, process: &str) -> String {
     format!("Failed {}: {:?}", process, err)
@@ -19,7 +21,7 @@ struct Claims {
     exp: usize,
 }
 
-pub fn get_jwt_expire(jwt: &str) -> Result<usize, String> {
+pub fn get_jwt_expire(jwt: &str) -> Result<usize, Error> {
     let mut val = Validation::default();
     val.insecure_disable_signature_validation();
     val.validate_exp = false;
@@ -28,7 +30,16 @@ pub fn get_jwt_expire(jwt: &str) -> Result<usize, String> {
         jwt,
         &DecodingKey::from_secret("".as_ref()),
         &val,
-    ).map_err(|err| make_err(Box::new(err), "decode jwt"))?;
+    )?;
 
     Ok(token_data.claims.exp)
 }
+
+pub fn is_jwt_expired(jwt: &str) -> bool {
+    let now = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs() as usize)
+        .unwrap_or_default();
+
+    matches!(get_jwt_expire(jwt), Ok(exp) if exp <= now)
+}
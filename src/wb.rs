@@ -1,82 +1,126 @@
+use std::collections::{HashMap, HashSet};
 use std::time::Duration;
-use reqwest::Client;
-use serde::Deserialize;
+use reqwest::{Client, Response, StatusCode};
+use serde::{Deserialize, Serialize};
 use std::io::Write;
 use serde_json::Value;
 use tempfile::NamedTempFile;
 use tokio::process::Command;
 use crate::calc::count_new_basic;
 use crate::db::product::Product;
+use crate::error::Error;
 use crate::utils;
 
 const JQ_QUERY: &str = r#"{
     supplier_id: (.data.products[0].supplierId // null),
     total: (.data.total // null),
-    prices: [.data.products[] | {id: .id, basic: .sizes[0].price.basic, total: .sizes[0].price.total}]
+    prices: [.data.products[] | {
+        id: .id,
+        basic: .sizes[0].price.basic,
+        product: .sizes[0].price.product,
+        total: .sizes[0].price.total
+    }]
 }"#;
 
+#[derive(Debug, Clone, Serialize)]
+pub struct PriceChange {
+    pub id: i32,
+    pub target_price: i32,
+    pub observed_basic: i32,
+    pub observed_total: i32,
+    pub new_price: i32,
+}
+
+pub struct PriceUpdate {
+    pub supplier_id: Option<i32>,
+    pub checked: usize,
+    pub observed: Vec<ProductPrice>,
+    /// requested goods WB returned no prices for
+    pub missing: Vec<i32>,
+    pub changes: Vec<PriceChange>,
+    pub uploaded: bool,
+}
+
+impl PriceUpdate {
+    pub fn products(&self) -> Vec<Product> {
+        self.changes
+            .iter()
+            .map(|change| Product::new(change.id, change.new_price))
+            .collect()
+    }
+}
+
 pub async fn calculate_and_set_price(
     supplier_id: Option<i32>,
     token: &str,
     products: Vec<Product>,
-) -> Result<(Option<i32>, Vec<Product>), String> {
-    let prices_page = get_prices(supplier_id, products.iter().map(|p| p.id).collect::<Vec<i32>>())
-        .await
-        .map_err(|err| utils::make_err(err, "get prices"))?;
+    dry_run: bool,
+) -> Result<PriceUpdate, Error> {
+    let prices_page = get_prices(supplier_id, products.iter().map(|p| p.id).collect::<Vec<i32>>()).await?;
 
-    let updated_products: Vec<(i32, Product)> = prices_page
+    let targets: HashMap<i32, i32> = products.iter().map(|p| (p.id, p.price)).collect();
+    let changes: Vec<PriceChange> = prices_page
         .prices
         .iter()
-        .zip(products.iter())
-        .map(|(product_price, product)| {
-            let target_price = product.price;
+        .filter_map(|product_price| {
+            let target_price = *targets.get(&product_price.id)?;
             let (discounted, new_price) = count_new_basic(target_price, product_price.total, product_price.basic);
-            (discounted, product_price.total, Product::new(product_price.id, new_price))
+            (product_price.total / 100 != discounted).then_some(PriceChange {
+                id: product_price.id,
+                target_price,
+                observed_basic: product_price.basic,
+                observed_total: product_price.total,
+                new_price,
+            })
         })
-        .filter(
-            |(discounted, total, _)| total / 100 != *discounted)
-        .map(|(discounted, _, product)| (discounted, product))
         .collect();
 
-    if updated_products.is_empty() {
-        return Ok((supplier_id, vec![]))
+    let observed: HashSet<i32> = prices_page.prices.iter().map(|p| p.id).collect();
+    let missing = products.iter().map(|p| p.id).filter(|id| !observed.contains(id)).collect();
+
+    let mut update = PriceUpdate {
+        supplier_id: prices_page.supplier_id.or(supplier_id),
+        checked: prices_page.prices.len(),
+        observed: prices_page.prices,
+        missing,
+        changes,
+        uploaded: false,
+    };
+
+    if !update.changes.is_empty() && !dry_run {
+        set_price(token, update.products()).await?;
+        update.uploaded = true;
     }
 
-    let to_update: Vec<Product> = updated_products.iter().map(|(_, p)| p.clone()).collect();
-    set_price(token, to_update.clone())
-        .await
-        .map_err(|_| "Error setting price.".to_string())?;
-
-    Ok((prices_page.supplier_id, to_update))
+    Ok(update)
 }
 
-pub async fn get_prices(supplier_id: Option<i32>, id_list: Vec<i32>) -> Result<ProductPricesPage, Box<dyn std::error::Error>> {
+pub async fn get_prices(supplier_id: Option<i32>, id_list: Vec<i32>) -> Result<ProductPricesPage, Error> {
     match id_list.len() {
         0 => Ok(ProductPricesPage::default()),
         1 => get_one_price(id_list[0]).await,
         _ => Ok(
-            get_supplier_catalog(
-                supplier_id
-                    .ok_or_else(
-                        || "Not available to get many prices without supplier_id".to_string()
-                    )?, None, None)
+            get_supplier_catalog(supplier_id.ok_or(Error::MissingSupplierId)?, None, None)
                 .await?
                 .with_goods(id_list)
         )
     }
 }
 
-async fn get_one_price(id: i32) -> Result<ProductPricesPage, Box<dyn std::error::Error>> {
+async fn get_one_price(id: i32) -> Result<ProductPricesPage, Error> {
     let url = format!("https://card.wb.ru/cards/v2/detail?curr=rub&dest=-1257786&nm={}", id);
     let data: Value = Client::new()
         .get(&url)
         .timeout(Duration::from_secs(60))
         .send()
-        .await?
+        .await
+        .map_err(Error::http("get price"))
+        .and_then(check_status)?
         .json()
-        .await?;
+        .await
+        .map_err(Error::http("parse get price response"))?;
 
-    Ok(parse_json(data).await?)
+    parse_json(data).await
 }
 
 #[derive(Deserialize, Debug, Default)]
@@ -103,12 +147,13 @@ impl ProductPricesPage {
 
 #[derive(Clone, Deserialize, Debug)]
 pub struct ProductPrice {
-    id: i32,
-    basic: i32,
-    total: i32,
+    pub id: i32,
+    pub basic: i32,
+    pub product: Option<i32>,
+    pub total: i32,
 }
 
-pub async fn get_supplier_catalog(supplier: i32, limit: Option<i32>, page: Option<i32>) -> Result<ProductPricesPage, String> {
+pub async fn get_supplier_catalog(supplier: i32, limit: Option<i32>, page: Option<i32>) -> Result<ProductPricesPage, Error> {
     let catalog_url = "https://catalog.wb.ru/sellers/v2/catalog";
     let url = format!(
         "{catalog_url}?curr=rub&dest=-1257786&sort=newly&supplier={supplier}&limit={}&page={}",
@@ -120,46 +165,62 @@ pub async fn get_supplier_catalog(supplier: i32, limit: Option<i32>, page: Optio
         .timeout(Duration::from_secs(60))
         .send()
         .await
-        .map_err(|err| utils::make_err(Box::new(err), "get catalog"))?
+        .map_err(Error::http("get catalog"))
+        .and_then(check_status)?
         .json()
         .await
-        .map_err(|err| utils::make_err(Box::new(err), "parse get catalog response"))?;
+        .map_err(Error::http("parse get catalog response"))?;
 
     parse_json(data).await
 }
 
-async fn parse_json(data: Value) -> Result<ProductPricesPage, String> {
+async fn parse_json(data: Value) -> Result<ProductPricesPage, Error> {
     let mut temp_file = NamedTempFile::new()
-        .map_err(|err| utils::make_err(Box::new(err), "create temporary file"))?;
+        .map_err(Error::io("create temporary file"))?;
     writeln!(temp_file, "{}", data)
-        .map_err(|err| utils::make_err(Box::new(err), "write to temporary file"))?;
+        .map_err(Error::io("write to temporary file"))?;
 
     let output = Command::new("jq")
         .arg(JQ_QUERY)
         .arg(temp_file.path())
         .output()
         .await
-        .map_err(|err| utils::make_err(Box::new(err), "execute jq"))?;
+        .map_err(Error::io("execute jq"))?;
 
     let filtered_json = String::from_utf8_lossy(&output.stdout);
-    Ok(serde_json::from_str(&filtered_json)
-        .map_err(|err| utils::make_err(Box::new(err), "parse filtered JSON"))?)
+    serde_json::from_str(&filtered_json)
+        .map_err(Error::json("parse filtered JSON"))
 }
 
-pub async fn set_price(token: &str, products: Vec<Product>) -> Result<(), reqwest::Error> {
+pub async fn set_price(token: &str, products: Vec<Product>) -> Result<(), Error> {
+    if utils::is_jwt_expired(token) {
+        return Err(Error::WbJwtExpired);
+    }
+
     let data = products.iter()
         .map(|product| serde_json::json!(
             { "nmID": product.id, "price": product.price }
         ))
         .collect::<Vec<_>>();
 
-    Client::new()
+    let response = Client::new()
         .post("https://discounts-prices-api.wildberries.ru/api/v2/upload/task")
         .timeout(Duration::from_secs(60))
         .header("Authorization", token)
         .json(&serde_json::json!({ "data": data }))
         .send()
-        .await?;
+        .await
+        .map_err(Error::http("set price"))?;
+    check_status(response)?;
 
     Ok(())
 }
+
+fn check_status(response: Response) -> Result<Response, Error> {
+    match response.status() {
+        status @ (StatusCode::UNAUTHORIZED | StatusCode::FORBIDDEN) => Err(Error::WbUnauthorized(status)),
+        StatusCode::TOO_MANY_REQUESTS => Err(Error::WbRateLimited),
+        status if !status.is_success() => Err(Error::WbStatus(status)),
+        _ => Ok(response),
+    }
+}